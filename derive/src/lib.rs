@@ -0,0 +1,535 @@
+//! `#[derive(Scriptable)]` for `rhai-specs_test`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, FnArg, ImplItem, ItemImpl, Lit, Meta, NestedMeta,
+    ReturnType,
+};
+
+/// Implement `ScriptableComponent` and `ScriptValue` for a struct with named fields.
+///
+/// The struct gets a rhai type named after it, a constructor taking every visible
+/// field in order, and a getter and setter for each visible field. Scripts can also
+/// pass an object map of the visible fields wherever the struct is expected. The
+/// same fields are available to rust through the reflection methods of
+/// `ScriptableComponent`.
+///
+/// Field attributes:
+/// - `#[scriptable(rename = "name")]` exposes the field to scripts as `name`
+/// - `#[scriptable(skip)]` hides the field, it is set to `Default::default()` by the constructor
+/// - `#[scriptable(readonly)]` only registers a getter for the field
+///
+/// The struct itself can be renamed with `#[scriptable(rename = "Name")]`, which is also
+/// the name it's registered under for `query`, `spawn` and `COMPONENT`. It can be bound to a
+/// script with `#[scriptable(script = "name")]` and put in a category of fallback
+/// scripts with `#[scriptable(category = "name")]`. `#[scriptable(methods)]` makes the
+/// methods of its `#[script_methods]` impl block callable, scripts call them like rust
+/// methods or by name with `component.invoke("name", [args])`.
+#[proc_macro_derive(Scriptable, attributes(scriptable))]
+pub fn derive_scriptable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match scriptable(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// the options given in `#[scriptable(...)]` attributes
+#[derive(Default)]
+struct Options {
+    rename: Option<String>,
+    category: Option<String>,
+    script: Option<String>,
+    skip: bool,
+    readonly: bool,
+    methods: bool,
+}
+
+impl Options {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = Options::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("scriptable")) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => return Err(syn::Error::new_spanned(meta, "expected `scriptable(...)`")),
+            };
+
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => {
+                        options.skip = true
+                    }
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("readonly") => {
+                        options.readonly = true
+                    }
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("methods") => {
+                        options.methods = true
+                    }
+                    NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("rename") => {
+                        options.rename = Some(string(value.lit)?)
+                    }
+                    NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("category") => {
+                        options.category = Some(string(value.lit)?)
+                    }
+                    NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("script") => {
+                        options.script = Some(string(value.lit)?)
+                    }
+                    nested => return Err(syn::Error::new_spanned(
+                        nested,
+                        "expected `rename`, `category`, `script`, `methods`, `skip` or `readonly`",
+                    )),
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn string(lit: Lit) -> syn::Result<String> {
+    match lit {
+        Lit::Str(string) => Ok(string.value()),
+        lit => Err(syn::Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+fn optional_str(string: Option<String>) -> TokenStream2 {
+    match string {
+        Some(string) => quote!(::std::option::Option::Some(#string)),
+        None => quote!(::std::option::Option::None),
+    }
+}
+
+fn scriptable(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let options = Options::parse(&input.attrs)?;
+    let methods = options.methods;
+    if options.skip || options.readonly {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "`skip` and `readonly` can only be used on fields",
+        ));
+    }
+    let type_name = options.rename.unwrap_or_else(|| ident.to_string());
+    let category = optional_str(options.category);
+    let script = optional_str(options.script);
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "`Scriptable` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "`Scriptable` can only be derived for structs",
+            ))
+        }
+    };
+
+    let krate = quote!(::rhai_specs_test);
+    let rhai = quote!(#krate::rhai);
+
+    let mut field_infos = Vec::new();
+    let mut constructor_args = Vec::new();
+    let mut constructor_fields = Vec::new();
+    let mut map_fields = Vec::new();
+    let mut getters = Vec::new();
+    let mut setters = Vec::new();
+    let mut accessors = Vec::new();
+
+    for field in fields {
+        let field_ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let options = Options::parse(&field.attrs)?;
+        if options.category.is_some() || options.script.is_some() || options.methods {
+            return Err(syn::Error::new_spanned(
+                field_ident,
+                "`category`, `script` and `methods` can only be used on the struct",
+            ));
+        }
+
+        if options.skip {
+            constructor_fields.push(quote!(#field_ident: ::std::default::Default::default()));
+            map_fields.push(quote!(#field_ident: ::std::default::Default::default()));
+            continue;
+        }
+
+        let name = options.rename.unwrap_or_else(|| field_ident.to_string());
+        let read_only = options.readonly;
+        field_infos.push(quote! {
+            #krate::Field {
+                name: #name,
+                type_name: ::std::stringify!(#ty),
+                read_only: #read_only,
+            }
+        });
+
+        constructor_args.push(quote!(#field_ident: #rhai::Dynamic));
+        constructor_fields
+            .push(quote!(#field_ident: <#ty as #krate::ScriptValue>::from_dynamic(#field_ident)?));
+        map_fields.push(quote! {
+            #field_ident: match map.remove(#name) {
+                ::std::option::Option::Some(value) => {
+                    <#ty as #krate::ScriptValue>::from_dynamic(value).map_err(|err| {
+                        #krate::ReflectError::invalid_value(#type_name, #name, err)
+                    })?
+                }
+                ::std::option::Option::None => {
+                    return ::std::result::Result::Err(#krate::ReflectError::MissingField {
+                        component: #type_name.into(),
+                        field: #name.into(),
+                    })
+                }
+            }
+        });
+
+        getters.push(quote! {
+            #name => ::std::result::Result::Ok(
+                <#ty as #krate::ScriptValue>::to_dynamic(&self.#field_ident),
+            ),
+        });
+        setters.push(if read_only {
+            quote! {
+                #name => ::std::result::Result::Err(#krate::ReflectError::ReadOnly {
+                    component: #type_name.into(),
+                    field: #name.into(),
+                }),
+            }
+        } else {
+            quote! {
+                #name => {
+                    self.#field_ident = <#ty as #krate::ScriptValue>::from_dynamic(value)
+                        .map_err(|err| #krate::ReflectError::invalid_value(#type_name, #name, err))?;
+                    ::std::result::Result::Ok(())
+                }
+            }
+        });
+
+        accessors.push(quote! {
+            engine.register_get(#name, |this: &mut #ident| {
+                <#ty as #krate::ScriptValue>::to_dynamic(&this.#field_ident)
+            });
+        });
+        if !read_only {
+            accessors.push(quote! {
+                engine.register_set_result(#name, |this: &mut #ident, value: #rhai::Dynamic| -> ::std::result::Result<(), ::std::boxed::Box<#rhai::EvalAltResult>> {
+                    this.#field_ident = <#ty as #krate::ScriptValue>::from_dynamic(value)?;
+                    ::std::result::Result::Ok(())
+                });
+            });
+        }
+    }
+
+    // the methods come from a `#[script_methods]` impl block
+    let register_methods = if methods {
+        quote! {
+            for method in <#ident as #krate::ScriptMethods>::methods() {
+                let name = method.name;
+                let mut arg_types = ::std::vec![::std::any::TypeId::of::<#ident>()];
+                arg_types.extend(method.params.iter().map(|_| ::std::any::TypeId::of::<#rhai::Dynamic>()));
+                engine.register_raw_fn(name, &arg_types, move |_, args| -> ::std::result::Result<#rhai::Dynamic, ::std::boxed::Box<#rhai::EvalAltResult>> {
+                    let (this, args) = args.split_first_mut().expect("bug: a method is called without `self`");
+                    let args: ::std::vec::Vec<#rhai::Dynamic> = args.iter().map(|arg| (**arg).clone()).collect();
+                    let mut this = this.write_lock::<#ident>().expect("bug: a method is called on the wrong type");
+                    ::std::result::Result::Ok(#krate::ScriptableComponent::call_method_mut(&mut *this, name, &args)?)
+                });
+            }
+        }
+    } else {
+        quote!()
+    };
+    let method_impls = if methods {
+        quote! {
+            fn methods(&self) -> &'static [#krate::Method] {
+                <#ident as #krate::ScriptMethods>::methods()
+            }
+
+            fn call_method(
+                &self,
+                name: &str,
+                args: &[#rhai::Dynamic],
+            ) -> ::std::result::Result<#rhai::Dynamic, #krate::ReflectError> {
+                <#ident as #krate::ScriptMethods>::call_method(self, name, args)
+            }
+
+            fn call_method_mut(
+                &mut self,
+                name: &str,
+                args: &[#rhai::Dynamic],
+            ) -> ::std::result::Result<#rhai::Dynamic, #krate::ReflectError> {
+                <#ident as #krate::ScriptMethods>::call_method_mut(self, name, args)
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+        impl #krate::ScriptValue for #ident {
+            fn to_dynamic(&self) -> #rhai::Dynamic {
+                #rhai::Dynamic::from(::std::clone::Clone::clone(self))
+            }
+
+            /// accepts the component itself or an object map of its fields
+            fn from_dynamic(
+                value: #rhai::Dynamic,
+            ) -> ::std::result::Result<Self, ::std::boxed::Box<#rhai::EvalAltResult>> {
+                if value.is::<#ident>() {
+                    return ::std::result::Result::Ok(value.cast::<#ident>());
+                }
+
+                let error = #krate::mismatch::<#ident>(&value);
+                let map = value.try_cast::<#rhai::Map>().ok_or(error)?;
+                ::std::result::Result::Ok(
+                    <#ident as #krate::ScriptableComponent>::from_map(map)?,
+                )
+            }
+        }
+
+        impl #krate::ScriptableComponent for #ident {
+            fn type_name(&self) -> &'static str {
+                #type_name
+            }
+
+            fn fields(&self) -> &'static [#krate::Field] {
+                &[#(#field_infos),*]
+            }
+
+            fn get_field(
+                &self,
+                name: &str,
+            ) -> ::std::result::Result<#rhai::Dynamic, #krate::ReflectError> {
+                match name {
+                    #(#getters)*
+                    _ => ::std::result::Result::Err(#krate::ReflectError::UnknownField {
+                        component: #type_name.into(),
+                        field: name.into(),
+                    }),
+                }
+            }
+
+            fn set_field(
+                &mut self,
+                name: &str,
+                value: #rhai::Dynamic,
+            ) -> ::std::result::Result<(), #krate::ReflectError> {
+                match name {
+                    #(#setters)*
+                    _ => {
+                        let _ = value;
+                        ::std::result::Result::Err(#krate::ReflectError::UnknownField {
+                            component: #type_name.into(),
+                            field: name.into(),
+                        })
+                    }
+                }
+            }
+
+            #method_impls
+
+            fn from_map(
+                mut map: #rhai::Map,
+            ) -> ::std::result::Result<Self, #krate::ReflectError> {
+                let component = #ident { #(#map_fields),* };
+                match map.into_iter().next() {
+                    ::std::option::Option::Some((field, _)) => {
+                        ::std::result::Result::Err(#krate::ReflectError::UnknownField {
+                            component: #type_name.into(),
+                            field: field.into(),
+                        })
+                    }
+                    ::std::option::Option::None => ::std::result::Result::Ok(component),
+                }
+            }
+
+            fn name() -> &'static str {
+                #type_name
+            }
+
+            fn script() -> ::std::option::Option<&'static str> {
+                #script
+            }
+
+            fn category() -> ::std::option::Option<&'static str> {
+                #category
+            }
+
+            fn register_rhai(engine: &mut #rhai::Engine) {
+                engine.register_type_with_name::<#ident>(#type_name);
+                engine.register_result_fn(#type_name, |#(#constructor_args),*| -> ::std::result::Result<#ident, ::std::boxed::Box<#rhai::EvalAltResult>> {
+                    ::std::result::Result::Ok(#ident { #(#constructor_fields),* })
+                });
+                #(#accessors)*
+                // `call` is taken by rhai's function pointers
+                engine.register_result_fn("invoke", |this: &mut #ident, name: #rhai::ImmutableString, args: #rhai::Array| -> ::std::result::Result<#rhai::Dynamic, ::std::boxed::Box<#rhai::EvalAltResult>> {
+                    ::std::result::Result::Ok(#krate::ScriptableComponent::call_method_mut(this, &name, &args)?)
+                });
+                #register_methods
+            }
+        }
+    })
+}
+
+/// Implement `ScriptMethods` for the methods in an `impl` block, so they can be called
+/// by name with `Dynamic` arguments. Use it with `#[scriptable(methods)]` on the struct.
+///
+/// Every method taking `&self` or `&mut self` is included. Arguments and return
+/// values are converted with `ScriptValue`.
+///
+/// Method attributes:
+/// - `#[scriptable(rename = "name")]` exposes the method to scripts as `name`
+/// - `#[scriptable(skip)]` leaves the method out
+#[proc_macro_attribute]
+pub fn script_methods(_attr: TokenStream, input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as ItemImpl);
+
+    match script_methods_impl(&mut input) {
+        Ok(tokens) => quote!(#input #tokens).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn script_methods_impl(input: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    let krate = quote!(::rhai_specs_test);
+    let rhai = quote!(#krate::rhai);
+    let self_ty = &input.self_ty;
+
+    let mut infos = Vec::new();
+    let mut shared_arms = Vec::new();
+    let mut mut_arms = Vec::new();
+
+    for item in &mut input.items {
+        let method = match item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+        let options = Options::parse(&method.attrs)?;
+        method
+            .attrs
+            .retain(|attr| !attr.path.is_ident("scriptable"));
+
+        let receiver = match method.sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) => receiver,
+            _ => continue,
+        };
+        if options.skip {
+            continue;
+        }
+        if receiver.reference.is_none() {
+            return Err(syn::Error::new_spanned(
+                receiver,
+                "script methods have to take `&self` or `&mut self`",
+            ));
+        }
+        let mutable = receiver.mutability.is_some();
+
+        let ident = &method.sig.ident;
+        let name = options.rename.unwrap_or_else(|| ident.to_string());
+        let params: Vec<_> = method
+            .sig
+            .inputs
+            .iter()
+            .skip(1)
+            .map(|arg| match arg {
+                FnArg::Typed(arg) => &arg.ty,
+                FnArg::Receiver(_) => unreachable!("only the first argument can be `self`"),
+            })
+            .collect();
+        let count = params.len();
+        let args = params.iter().enumerate().map(|(index, ty)| {
+            quote! {
+                <#ty as #krate::ScriptValue>::from_dynamic(::std::clone::Clone::clone(&args[#index]))
+                    .map_err(|err| #krate::ReflectError::invalid_argument(component, #name, #index, err))?
+            }
+        });
+        let call = match &method.sig.output {
+            ReturnType::Default => quote! {
+                self.#ident(#(#args),*);
+                ::std::result::Result::Ok(#rhai::Dynamic::UNIT)
+            },
+            ReturnType::Type(..) => quote! {
+                let result = self.#ident(#(#args),*);
+                ::std::result::Result::Ok(#krate::ScriptValue::to_dynamic(&result))
+            },
+        };
+        let arm = quote! {
+            #name => {
+                if args.len() != #count {
+                    return ::std::result::Result::Err(#krate::ReflectError::WrongArgumentCount {
+                        component: component.into(),
+                        method: #name.into(),
+                        expected: #count,
+                        found: args.len(),
+                    });
+                }
+                #call
+            }
+        };
+
+        infos.push(quote! {
+            #krate::Method {
+                name: #name,
+                params: &[#(::std::stringify!(#params)),*],
+                mutable: #mutable,
+            }
+        });
+        if mutable {
+            shared_arms.push(quote! {
+                #name => ::std::result::Result::Err(#krate::ReflectError::Immutable {
+                    component: component.into(),
+                    method: #name.into(),
+                }),
+            });
+            mut_arms.push(arm);
+        } else {
+            shared_arms.push(arm);
+        }
+    }
+
+    Ok(quote! {
+        impl #krate::ScriptMethods for #self_ty {
+            fn methods() -> &'static [#krate::Method] {
+                &[#(#infos),*]
+            }
+
+            #[allow(unused_variables)]
+            fn call_method(
+                &self,
+                name: &str,
+                args: &[#rhai::Dynamic],
+            ) -> ::std::result::Result<#rhai::Dynamic, #krate::ReflectError> {
+                let component = #krate::ScriptableComponent::type_name(self);
+                match name {
+                    #(#shared_arms)*
+                    _ => ::std::result::Result::Err(#krate::ReflectError::UnknownMethod {
+                        component: component.into(),
+                        method: name.into(),
+                    }),
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn call_method_mut(
+                &mut self,
+                name: &str,
+                args: &[#rhai::Dynamic],
+            ) -> ::std::result::Result<#rhai::Dynamic, #krate::ReflectError> {
+                let component = #krate::ScriptableComponent::type_name(self);
+                match name {
+                    #(#mut_arms)*
+                    _ => <Self as #krate::ScriptMethods>::call_method(self, name, args),
+                }
+            }
+        }
+    })
+}
@@ -8,7 +8,7 @@ fn test_basic_script_functionality() {
 
     let mut scripts = Vec::new();
     scripts.push(load_script(
-        r#"C:\Users\jackc\CLionProjects\rhai-specs_test\scripts\test.rhai"#
+        concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/test.rhai")
             .parse()
             .unwrap(),
         &engine
@@ -0,0 +1,327 @@
+use crate::context::with_context;
+use crate::{dynamic, limits, mismatch, resource, ResourceTable, ResourceTableError, ScriptError};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, INT};
+use specs::prelude::*;
+
+/// Register the functions scripts use to reach the world:
+///
+/// - `query(["Position", "Velocity"])` returns the entities that have all of the components
+/// - `query_changed(["Position", "Velocity"])` returns the ones among them where any of the
+///   components was inserted or modified since the script last ran, for components
+///   registered with `WorldHelper::track_changes`
+/// - `entity["Position"]` reads a copy of an entity's component, and
+///   `entity["Position"] = value` (or `entity["Position"].x = 1.0`) writes it back
+/// - `entity.has("Position")` checks whether an entity has a component
+/// - `spawn(#{ Position: #{x: 1.0, y: 2.0} })` creates an entity with the given components
+/// - `despawn(entity)` deletes an entity
+/// - `resource("GameTime")` and `resource_mut("Score")` reach a resource registered with
+///   `WorldHelper::insert_scriptable_resource`, `resource_mut("Score").points += 1`
+///   changes it in the world
+/// - `define_resource("Weather", #{rain: 0.0, wind: 3})` adds a `ScriptableResource` when
+///   the world is maintained, scripts whose systems are created afterwards can declare it
+/// - `register_component("Health", #{hp: 100, max: 100})` adds a `DynamicComponent` with
+///   those fields and defaults the same way, `spawn(#{ Health: #{hp: 50} })` fills in the
+///   missing fields
+///
+/// Scripts can only use components and resources their system declared in its reads
+/// or writes.
+/// Spawning and despawning is deferred until the world is maintained, so it doesn't
+/// need to be declared.
+///
+/// It also has the engine hold scripts to the `ScriptLimits` they declare.
+pub fn register_world_api(engine: &mut Engine) {
+    limits::install_hooks(engine, None);
+    engine
+        .register_type_with_name::<Entity>("Entity")
+        .register_get("id", |entity: &mut Entity| entity.id() as INT)
+        .register_fn("to_string", |entity: &mut Entity| entity_to_string(*entity))
+        .register_fn("to_debug", |entity: &mut Entity| entity_to_string(*entity))
+        .register_result_fn("has", |entity: &mut Entity, name: ImmutableString| {
+            has(*entity, &name)
+        })
+        .register_indexer_get_result(|entity: &mut Entity, name: ImmutableString| {
+            get(*entity, &name)
+        })
+        .register_indexer_set_result(
+            |entity: &mut Entity, name: ImmutableString, value: Dynamic| set(*entity, &name, value),
+        )
+        .register_result_fn("despawn", despawn)
+        .register_result_fn("register_component", register_component)
+        .register_result_fn("query", query)
+        .register_result_fn("query", |name: ImmutableString| {
+            query(vec![Dynamic::from(name)])
+        })
+        .register_result_fn("query_changed", query_changed)
+        .register_result_fn("query_changed", |name: ImmutableString| {
+            query_changed(vec![Dynamic::from(name)])
+        });
+
+    engine
+        .register_type_with_name::<ResourceView>("Resource")
+        .register_fn("to_string", |view: &mut ResourceView| view.to_string())
+        .register_fn("to_debug", |view: &mut ResourceView| view.to_string())
+        .register_result_fn("resource", |name: ImmutableString| {
+            ResourceView::new(name, false)
+        })
+        .register_result_fn("resource_mut", |name: ImmutableString| {
+            ResourceView::new(name, true)
+        })
+        .register_result_fn("to_map", |view: &mut ResourceView| view.to_map())
+        .register_indexer_get_result(|view: &mut ResourceView, field: ImmutableString| {
+            view.get(&field)
+        })
+        .register_indexer_set_result(
+            |view: &mut ResourceView, field: ImmutableString, value: Dynamic| {
+                view.set(&field, value)
+            },
+        )
+        .register_result_fn("define_resource", define_resource)
+        .register_result_fn(
+            "invoke",
+            |view: &mut ResourceView, method: ImmutableString, args: Array| {
+                view.invoke(&method, &args)
+            },
+        );
+
+    // `spawn` is a reserved keyword in rhai, so it can't be a plain function. The
+    // custom syntax nests deeper than a call, and rhai's default depth for function
+    // bodies is too shallow for `spawn(#{ Position: #{ x: 1.0 } })` inside `update`
+    if engine.max_function_expr_depth() < 32 {
+        let depth = engine.max_expr_depth().max(64);
+        engine.set_max_expr_depths(depth, 32);
+    }
+    engine
+        .register_custom_syntax(["spawn", "(", "$expr$", ")"], false, |context, inputs| {
+            let components = context.eval_expression_tree(&inputs[0])?;
+            let error = mismatch::<Map>(&components);
+            let components = components.try_cast::<Map>().ok_or(error)?;
+            spawn(components).map(Dynamic::from)
+        })
+        .expect("bug: invalid `spawn` syntax");
+}
+
+fn entity_to_string(entity: Entity) -> String {
+    format!("Entity({}, {})", entity.id(), entity.gen().id())
+}
+
+/// every living entity that has all of the named components
+fn query(names: Array) -> Result<Array, Box<EvalAltResult>> {
+    query_with(names, false)
+}
+
+/// like `query`, but only the entities where any of the components changed
+fn query_changed(names: Array) -> Result<Array, Box<EvalAltResult>> {
+    query_with(names, true)
+}
+
+fn query_with(names: Array, changed_only: bool) -> Result<Array, Box<EvalAltResult>> {
+    let names = names
+        .into_iter()
+        .map(|name| name.into_string())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|actual| format!("query expects component names, got {}", actual))?;
+
+    with_context(|context| {
+        let mut mask: Option<BitSet> = None;
+        for name in &names {
+            let (access, storage) = context.read(name)?;
+            let component_mask = access.mask(storage, context.entities);
+            match &mut mask {
+                Some(mask) => *mask &= &component_mask,
+                None => mask = Some(component_mask),
+            }
+        }
+        if changed_only {
+            let mut changed = BitSet::new();
+            for name in &names {
+                changed |= context.changed(name)?;
+            }
+            if let Some(mask) = &mut mask {
+                *mask &= &changed;
+            }
+        }
+
+        Ok(mask
+            .map(|mask| {
+                (&mask)
+                    .join()
+                    .map(|id| context.entities.entity(id))
+                    .filter(|entity| context.entities.is_alive(*entity))
+                    .map(Dynamic::from)
+                    .collect()
+            })
+            .unwrap_or_default())
+    })
+}
+
+fn has(entity: Entity, name: &str) -> Result<bool, Box<EvalAltResult>> {
+    with_context(|context| {
+        let (access, storage) = context.read(name)?;
+        Ok(access.get(storage, context.entities, entity).is_some())
+    })
+}
+
+fn get(entity: Entity, name: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+    with_context(|context| {
+        let (access, storage) = context.read(name)?;
+        access
+            .get(storage, context.entities, entity)
+            .ok_or_else(|| format!("{} has no `{}`", entity_to_string(entity), name).into())
+    })
+}
+
+fn set(entity: Entity, name: &str, value: Dynamic) -> Result<(), Box<EvalAltResult>> {
+    with_context(|context| {
+        let entities = context.entities;
+        let (access, storage) = context.write(name)?;
+        access.set(storage, entities, entity, value)
+    })
+}
+
+/// create an entity now and add its components when the world is maintained
+fn spawn(components: Map) -> Result<Entity, Box<EvalAltResult>> {
+    with_context(|context| {
+        // check every component first so a bad one doesn't leave a half built entity
+        let components = components
+            .into_iter()
+            .map(|(name, value)| Ok((context.component(&name)?, value)))
+            .collect::<Result<Vec<_>, Box<EvalAltResult>>>()?;
+
+        let entity = context.entities.create();
+        for (access, value) in components {
+            if let Err(err) = access.insert_lazy(context.lazy, entity, value) {
+                let _ = context.entities.delete(entity);
+                return Err(err);
+            }
+        }
+
+        Ok(entity)
+    })
+}
+
+/// delete an entity when the world is maintained
+fn despawn(entity: Entity) -> Result<(), Box<EvalAltResult>> {
+    with_context(|context| {
+        context
+            .entities
+            .delete(entity)
+            .map_err(|err| err.to_string().into())
+    })
+}
+
+/// add a runtime resource when the world is maintained
+fn define_resource(name: ImmutableString, fields: Map) -> Result<(), Box<EvalAltResult>> {
+    add_when_maintained("resource", name, move |world, name| {
+        resource::define_resource(world, name, fields)
+    })
+}
+
+/// add a dynamic component when the world is maintained
+fn register_component(name: ImmutableString, schema: Map) -> Result<(), Box<EvalAltResult>> {
+    add_when_maintained("component", name, move |world, name| {
+        dynamic::register_dynamic_component(world, name, schema)
+    })
+}
+
+/// reserve `name` and `add` the `kind` of resource called `name` when the world is
+/// maintained. If adding it fails then, the error is reported by the script's system.
+fn add_when_maintained(
+    kind: &'static str,
+    name: ImmutableString,
+    add: impl FnOnce(&mut World, &str) -> Result<ResourceId, ResourceTableError> + Send + Sync + 'static,
+) -> Result<(), Box<EvalAltResult>> {
+    with_context(|context| {
+        // reserved right away, so a second script can't define it in the same frame
+        if !context.resources.reserve(&name) {
+            return Err(format!("the {} `{}` already exists", kind, name).into());
+        }
+
+        let script = context.script.to_owned();
+        let deferred = context.deferred.clone();
+        context.lazy.exec_mut(move |world| {
+            let result = add(world, &name);
+            world.fetch::<ResourceTable>().release(&name);
+            if let Err(err) = result {
+                deferred.push(ScriptError::Runtime {
+                    script,
+                    message: format!("couldn't add the {} `{}`: {}", kind, name, err),
+                    position: rhai::Position::NONE,
+                });
+            }
+        });
+        Ok(())
+    })
+}
+
+/// A script's handle on a resource. Fields are read from and written to the world
+/// through the reflection table each time they're used.
+#[derive(Clone, Debug)]
+struct ResourceView {
+    name: ImmutableString,
+    mutable: bool,
+}
+
+impl ResourceView {
+    /// check the script declared the access it asks for
+    fn new(name: ImmutableString, mutable: bool) -> Result<Self, Box<EvalAltResult>> {
+        with_context(|context| {
+            if mutable {
+                context.write_resource(&name)?;
+            } else {
+                context.read_resource(&name)?;
+            }
+            Ok(())
+        })?;
+
+        Ok(ResourceView { name, mutable })
+    }
+
+    fn get(&self, field: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        with_context(|context| Ok(context.read_resource(&self.name)?.get_field(field)?))
+    }
+
+    fn set(&self, field: &str, value: Dynamic) -> Result<(), Box<EvalAltResult>> {
+        self.check_mutable()?;
+        with_context(|context| {
+            Ok(context
+                .write_resource(&self.name)?
+                .set_field(field, value)?)
+        })
+    }
+
+    fn to_map(&self) -> Result<Map, Box<EvalAltResult>> {
+        with_context(|context| Ok(context.read_resource(&self.name)?.to_map()))
+    }
+
+    fn invoke(&self, method: &str, args: &[Dynamic]) -> Result<Dynamic, Box<EvalAltResult>> {
+        with_context(|context| {
+            if self.mutable {
+                Ok(context
+                    .write_resource(&self.name)?
+                    .call_method_mut(method, args)?)
+            } else {
+                Ok(context
+                    .read_resource(&self.name)?
+                    .call_method(method, args)?)
+            }
+        })
+    }
+
+    fn check_mutable(&self) -> Result<(), Box<EvalAltResult>> {
+        if self.mutable {
+            Ok(())
+        } else {
+            Err(format!(
+                "`{}` was fetched with `resource`, use `resource_mut` to change it",
+                self.name
+            )
+            .into())
+        }
+    }
+}
+
+impl std::fmt::Display for ResourceView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Resource({})", self.name)
+    }
+}
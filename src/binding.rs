@@ -0,0 +1,180 @@
+use crate::Script;
+use rhai::INT;
+use std::fmt;
+
+/// A script bound to a component, see `WorldHelper::scripts_for`.
+///
+/// A component's scripts run in order of priority, lowest first, so a script
+/// layered on top of another (a game mode overriding the base behaviour) should
+/// declare a higher priority.
+#[derive(Clone, Debug)]
+pub struct ComponentScript {
+    script: Script,
+    pub(crate) priority: INT,
+    enabled: bool,
+}
+
+impl ComponentScript {
+    pub(crate) fn new(script: Script, priority: INT) -> Self {
+        ComponentScript {
+            script,
+            priority,
+            enabled: true,
+        }
+    }
+
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    pub fn script_mut(&mut self) -> &mut Script {
+        &mut self.script
+    }
+
+    pub fn priority(&self) -> INT {
+        self.priority
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+/// How a scriptable component got its script, see `WorldHelper::report`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptBinding {
+    /// scripts were found for the component, these are their names in order
+    Bound { scripts: Vec<String> },
+    /// no script was found, the fallback for the component's category is used
+    Category { category: String, script: String },
+    /// no script was found, the default script is used
+    Default { script: String },
+    /// no script was found and there was nothing to fall back to
+    Unbound,
+}
+
+impl ScriptBinding {
+    /// the scripts the component ended up with
+    pub fn scripts(&self) -> Vec<&str> {
+        match self {
+            ScriptBinding::Bound { scripts } => scripts.iter().map(String::as_str).collect(),
+            ScriptBinding::Category { script, .. } | ScriptBinding::Default { script } => {
+                vec![script]
+            }
+            ScriptBinding::Unbound => Vec::new(),
+        }
+    }
+
+    /// whether the component is using a fallback instead of its own script
+    pub fn is_fallback(&self) -> bool {
+        matches!(
+            self,
+            ScriptBinding::Category { .. } | ScriptBinding::Default { .. }
+        )
+    }
+}
+
+impl fmt::Display for ScriptBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptBinding::Bound { scripts } => write!(f, "{}", scripts.join(", ")),
+            ScriptBinding::Category { category, script } => {
+                write!(f, "{} (fallback for `{}`)", script, category)
+            }
+            ScriptBinding::Default { script } => write!(f, "{} (default)", script),
+            ScriptBinding::Unbound => write!(f, "no script"),
+        }
+    }
+}
+
+/// The script every registered scriptable component was bound to, in registration order.
+#[derive(Clone, Debug, Default)]
+pub struct BindingReport {
+    bindings: Vec<(String, ScriptBinding)>,
+}
+
+impl BindingReport {
+    pub(crate) fn record(&mut self, component: &str, binding: ScriptBinding) {
+        self.bindings.retain(|(name, _)| name != component);
+        self.bindings.push((component.to_owned(), binding));
+    }
+
+    pub fn get(&self, component: &str) -> Option<&ScriptBinding> {
+        self.bindings
+            .iter()
+            .find(|(name, _)| name == component)
+            .map(|(_, binding)| binding)
+    }
+
+    /// every component and its binding
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ScriptBinding)> {
+        self.bindings
+            .iter()
+            .map(|(name, binding)| (name.as_str(), binding))
+    }
+
+    /// the components using a fallback script
+    pub fn fallbacks(&self) -> impl Iterator<Item = (&str, &ScriptBinding)> {
+        self.iter().filter(|(_, binding)| binding.is_fallback())
+    }
+
+    /// the components without any script
+    pub fn unbound(&self) -> impl Iterator<Item = &str> {
+        self.iter()
+            .filter(|(_, binding)| **binding == ScriptBinding::Unbound)
+            .map(|(name, _)| name)
+    }
+}
+
+impl fmt::Display for BindingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (component, binding) in self.iter() {
+            writeln!(f, "{} -> {}", component, binding)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a scriptable component couldn't be bound to a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindingError {
+    /// scripts claiming the component have the same priority, so their order is unclear
+    Ambiguous {
+        component: String,
+        scripts: Vec<String>,
+    },
+    /// the component names a script that wasn't added
+    MissingScript { component: String, script: String },
+    /// the script updates each entity on its own, like `update(entity, delta)`, so it's
+    /// attached to entities with a `ScriptInstance` instead
+    PerEntity { component: String, script: String },
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingError::Ambiguous { component, scripts } => write!(
+                f,
+                "scripts bound to {} have the same priority: {}",
+                component,
+                scripts.join(", ")
+            ),
+            BindingError::MissingScript { component, script } => write!(
+                f,
+                "{} is bound to the script {}, which wasn't added",
+                component, script
+            ),
+            BindingError::PerEntity { component, script } => write!(
+                f,
+                "{} can't be bound to the script {}, which updates each entity on its own",
+                component, script
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BindingError {}
@@ -0,0 +1,252 @@
+use crate::ScriptValue;
+use rhai::{Dynamic, EvalAltResult};
+use specs::prelude::*;
+use specs::shred::Fetch;
+use specs::storage::MaskedStorage;
+use specs::world::EntitiesRes;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Type-erased access to the storage of a component scripts can use.
+///
+/// The storage is passed in as the `Resource` a script system fetched for it, so
+/// scripts only reach the storages their system declared.
+pub trait ComponentAccess: Send + Sync {
+    /// the id of the resource holding the component's storage
+    fn storage_id(&self) -> ResourceId;
+
+    /// the entities that have the component
+    fn mask(&self, storage: &dyn Resource, entities: &Fetch<EntitiesRes>) -> BitSet;
+
+    /// a copy of the entity's component, if it has one
+    fn get(
+        &self,
+        storage: &dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+    ) -> Option<Dynamic>;
+
+    /// insert or replace the entity's component
+    fn set(
+        &self,
+        storage: &mut dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>>;
+
+    /// insert the component into `entity` the next time the world is maintained
+    fn insert_lazy(
+        &self,
+        lazy: &LazyUpdate,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>>;
+
+    /// start tracking changes to the component, `None` if its storage isn't flagged
+    /// or it was registered without tracking
+    fn register_reader(
+        &self,
+        storage: &mut dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+    ) -> Option<ReaderId<ComponentEvent>> {
+        let _ = (storage, entities);
+        None
+    }
+
+    /// the entities whose component was inserted or modified since `reader` last read
+    fn changed(
+        &self,
+        storage: &dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        reader: &mut ReaderId<ComponentEvent>,
+    ) -> BitSet {
+        let _ = (storage, entities, reader);
+        BitSet::new()
+    }
+}
+
+/// `ComponentAccess` for a rust component type.
+struct Binding<C>(PhantomData<fn() -> C>);
+
+impl<C> Binding<C>
+where
+    C: Component,
+{
+    fn storage<'a>(
+        storage: &'a dyn Resource,
+        entities: &Fetch<'a, EntitiesRes>,
+    ) -> Storage<'a, C, &'a MaskedStorage<C>> {
+        let storage = storage
+            .downcast_ref::<MaskedStorage<C>>()
+            .expect("bug: component storage has the wrong type");
+        Storage::new(entities.clone(), storage)
+    }
+}
+
+impl<C> ComponentAccess for Binding<C>
+where
+    C: Component + ScriptValue + Send + Sync,
+{
+    fn storage_id(&self) -> ResourceId {
+        ResourceId::new::<MaskedStorage<C>>()
+    }
+
+    fn mask(&self, storage: &dyn Resource, entities: &Fetch<EntitiesRes>) -> BitSet {
+        Self::storage(storage, entities).mask().clone()
+    }
+
+    fn get(
+        &self,
+        storage: &dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+    ) -> Option<Dynamic> {
+        Self::storage(storage, entities)
+            .get(entity)
+            .map(ScriptValue::to_dynamic)
+    }
+
+    fn set(
+        &self,
+        storage: &mut dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let value = C::from_dynamic(value)?;
+        let storage = storage
+            .downcast_mut::<MaskedStorage<C>>()
+            .expect("bug: component storage has the wrong type");
+
+        Storage::new(entities.clone(), storage)
+            .insert(entity, value)
+            .map(|_| ())
+            .map_err(|err| err.to_string().into())
+    }
+
+    fn insert_lazy(
+        &self,
+        lazy: &LazyUpdate,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>> {
+        lazy.insert(entity, C::from_dynamic(value)?);
+        Ok(())
+    }
+}
+
+/// `ComponentAccess` for a rust component type with a flagged storage, which also
+/// tracks changes to it.
+struct TrackedBinding<C>(Binding<C>);
+
+impl<C> ComponentAccess for TrackedBinding<C>
+where
+    C: Component + ScriptValue + Send + Sync,
+    C::Storage: Tracked,
+{
+    fn storage_id(&self) -> ResourceId {
+        self.0.storage_id()
+    }
+
+    fn mask(&self, storage: &dyn Resource, entities: &Fetch<EntitiesRes>) -> BitSet {
+        self.0.mask(storage, entities)
+    }
+
+    fn get(
+        &self,
+        storage: &dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+    ) -> Option<Dynamic> {
+        self.0.get(storage, entities, entity)
+    }
+
+    fn set(
+        &self,
+        storage: &mut dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>> {
+        self.0.set(storage, entities, entity, value)
+    }
+
+    fn insert_lazy(
+        &self,
+        lazy: &LazyUpdate,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>> {
+        self.0.insert_lazy(lazy, entity, value)
+    }
+
+    fn register_reader(
+        &self,
+        storage: &mut dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+    ) -> Option<ReaderId<ComponentEvent>> {
+        let storage = storage
+            .downcast_mut::<MaskedStorage<C>>()
+            .expect("bug: component storage has the wrong type");
+        Some(Storage::new(entities.clone(), storage).register_reader())
+    }
+
+    fn changed(
+        &self,
+        storage: &dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        reader: &mut ReaderId<ComponentEvent>,
+    ) -> BitSet {
+        Binding::<C>::storage(storage, entities)
+            .channel()
+            .read(reader)
+            .filter_map(|event| match *event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => Some(id),
+                ComponentEvent::Removed(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Maps component names to their storages, so scripts can look components up by name.
+#[derive(Default)]
+pub struct ComponentTable {
+    map: HashMap<String, Box<dyn ComponentAccess>>,
+}
+
+impl ComponentTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// make the component `C` available to scripts as `name`
+    pub fn register<C>(&mut self, name: &str)
+    where
+        C: Component + ScriptValue + Send + Sync,
+    {
+        self.register_access(name, Box::new(Binding::<C>(PhantomData)));
+    }
+
+    /// like `register`, but let scripts ask which entities' `C` changed since they
+    /// last ran, with `query_changed`
+    pub fn register_tracked<C>(&mut self, name: &str)
+    where
+        C: Component + ScriptValue + Send + Sync,
+        C::Storage: Tracked,
+    {
+        self.register_access(name, Box::new(TrackedBinding::<C>(Binding(PhantomData))));
+    }
+
+    pub fn register_access(&mut self, name: &str, access: Box<dyn ComponentAccess>) {
+        self.map.insert(name.to_owned(), access);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ComponentAccess> {
+        self.map.get(name).map(Box::as_ref)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(String::as_str)
+    }
+}
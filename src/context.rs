@@ -0,0 +1,247 @@
+use crate::{
+    ComponentAccess, ComponentTable, Dependencies, ReflectionTable, ResourceTable, ScriptError,
+    ScriptSystemData, ScriptableComponent,
+};
+use rhai::EvalAltResult;
+use specs::prelude::*;
+use specs::shred::cell::{Ref, RefMut};
+use specs::shred::Fetch;
+use specs::world::EntitiesRes;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ptr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// What a running script can reach in the world: the resources and storages its
+/// system fetched.
+pub(crate) struct ScriptContext<'a> {
+    pub(crate) script: &'a str,
+    pub(crate) entities: &'a Fetch<'a, EntitiesRes>,
+    pub(crate) lazy: &'a LazyUpdate,
+    components: &'a ComponentTable,
+    pub(crate) resources: &'a ResourceTable,
+    meta_table: &'a ReflectionTable,
+    reads: HashMap<ResourceId, &'a dyn Resource>,
+    writes: HashMap<ResourceId, &'a mut dyn Resource>,
+    /// the entities whose tracked components changed since the script last ran
+    pub(crate) changed: HashMap<String, BitSet>,
+    /// where the work the script deferred to `World::maintain` reports its failures
+    pub(crate) deferred: Deferred,
+}
+
+/// The failures of the work a script deferred to `World::maintain`, collected for its
+/// system to report on its next run.
+#[derive(Clone, Default)]
+pub(crate) struct Deferred(Arc<Mutex<Vec<ScriptError>>>);
+
+impl Deferred {
+    pub(crate) fn push(&self, error: ScriptError) {
+        self.errors().push(error);
+    }
+
+    /// the failures since the last time they were taken
+    pub(crate) fn take(&self) -> Vec<ScriptError> {
+        std::mem::take(&mut *self.errors())
+    }
+
+    fn errors(&self) -> std::sync::MutexGuard<'_, Vec<ScriptError>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'a> ScriptContext<'a> {
+    /// `writes` are the writes taken out of `data`, so they can be borrowed mutably
+    /// alongside the rest of it
+    pub(crate) fn new(
+        script: &'a str,
+        dependencies: &Dependencies,
+        data: &'a ScriptSystemData<'a>,
+        writes: &'a mut [RefMut<'_, Box<dyn Resource>>],
+    ) -> Self {
+        // `ScriptSystemData` fetches the resources in the order the dependencies list them
+        let reads = dependencies
+            .reads
+            .iter()
+            .cloned()
+            .zip(data.reads.iter().map(|res: &Ref<_>| Box::as_ref(res)))
+            .collect();
+        let writes = dependencies
+            .writes
+            .iter()
+            .cloned()
+            .zip(writes.iter_mut().map(|res| Box::as_mut(res)))
+            .collect();
+
+        ScriptContext {
+            script,
+            entities: &data.entities,
+            lazy: &data.lazy,
+            components: &data.components,
+            resources: &data.resources,
+            meta_table: &data.meta_table,
+            reads,
+            writes,
+            changed: HashMap::new(),
+            deferred: Deferred::default(),
+        }
+    }
+
+    pub(crate) fn component(
+        &self,
+        name: &str,
+    ) -> Result<&'a dyn ComponentAccess, Box<EvalAltResult>> {
+        self.components
+            .get(name)
+            .ok_or_else(|| format!("unknown component `{}`", name).into())
+    }
+
+    /// the binding and storage of a component the script declared it reads or writes
+    pub(crate) fn read(
+        &self,
+        name: &str,
+    ) -> Result<(&'a dyn ComponentAccess, &dyn Resource), Box<EvalAltResult>> {
+        let access = self.component(name)?;
+        let id = access.storage_id();
+
+        match (self.reads.get(&id), self.writes.get(&id)) {
+            (Some(storage), _) => Ok((access, *storage)),
+            (None, Some(storage)) => Ok((access, &**storage)),
+            (None, None) => Err(format!(
+                "script `{}` did not declare that it reads `{}`",
+                self.script, name
+            )
+            .into()),
+        }
+    }
+
+    /// the binding and storage of a component the script declared it writes
+    pub(crate) fn write(
+        &mut self,
+        name: &str,
+    ) -> Result<(&'a dyn ComponentAccess, &mut dyn Resource), Box<EvalAltResult>> {
+        let access = self.component(name)?;
+        let script = self.script;
+
+        match self.writes.get_mut(&access.storage_id()) {
+            Some(storage) => Ok((access, &mut **storage)),
+            None => Err(format!(
+                "script `{}` did not declare that it writes `{}`",
+                script, name
+            )
+            .into()),
+        }
+    }
+
+    /// the entities whose component `name` changed since the script last ran
+    pub(crate) fn changed(&self, name: &str) -> Result<&BitSet, Box<EvalAltResult>> {
+        self.changed.get(name).ok_or_else(|| {
+            format!(
+                "changes to `{}` aren't tracked for script `{}`, it has to be declared and \
+                 registered with `WorldHelper::track_changes`",
+                name, self.script
+            )
+            .into()
+        })
+    }
+
+    /// the id of a resource scripts can reflect on
+    fn resource_id(&self, name: &str) -> Result<ResourceId, Box<EvalAltResult>> {
+        self.resources
+            .get(name)
+            .ok_or_else(|| format!("unknown resource `{}`", name).into())
+    }
+
+    fn reflect_error(name: &str) -> Box<EvalAltResult> {
+        format!(
+            "the resource `{}` isn't registered in the reflection table",
+            name
+        )
+        .into()
+    }
+
+    /// a resource the script declared it reads or writes
+    pub(crate) fn read_resource(
+        &self,
+        name: &str,
+    ) -> Result<&dyn ScriptableComponent, Box<EvalAltResult>> {
+        let id = self.resource_id(name)?;
+        let resource = match (self.reads.get(&id), self.writes.get(&id)) {
+            (Some(resource), _) => *resource,
+            (None, Some(resource)) => &**resource,
+            (None, None) => {
+                return Err(format!(
+                    "script `{}` did not declare that it reads `{}`",
+                    self.script, name
+                )
+                .into())
+            }
+        };
+
+        self.meta_table
+            .get(resource)
+            .ok_or_else(|| Self::reflect_error(name))
+    }
+
+    /// a resource the script declared it writes
+    pub(crate) fn write_resource(
+        &mut self,
+        name: &str,
+    ) -> Result<&mut (dyn ScriptableComponent + 'static), Box<EvalAltResult>> {
+        let id = self.resource_id(name)?;
+        let meta_table = self.meta_table;
+        let resource = match self.writes.get_mut(&id) {
+            Some(resource) => &mut **resource,
+            None => {
+                return Err(format!(
+                    "script `{}` did not declare that it writes `{}`",
+                    self.script, name
+                )
+                .into())
+            }
+        };
+
+        meta_table
+            .get_mut(resource)
+            .ok_or_else(|| Self::reflect_error(name))
+    }
+}
+
+thread_local! {
+    /// the context of the script running on this thread, if any
+    static CURRENT: Cell<*mut ()> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// puts a pointer back into `CURRENT` when dropped
+struct Restore(*mut ());
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.0));
+    }
+}
+
+/// make `context` available to the world api while `f` runs
+pub(crate) fn enter<R>(context: &mut ScriptContext<'_>, f: impl FnOnce() -> R) -> R {
+    let context = context as *mut ScriptContext<'_> as *mut ();
+    let _restore = Restore(CURRENT.with(|current| current.replace(context)));
+
+    f()
+}
+
+/// run `f` with the context of the script currently running on this thread
+pub(crate) fn with_context<R>(
+    f: impl FnOnce(&mut ScriptContext<'_>) -> Result<R, Box<EvalAltResult>>,
+) -> Result<R, Box<EvalAltResult>> {
+    // take the pointer out while `f` runs so a nested call can't get a second `&mut`
+    let context = CURRENT.with(|current| current.replace(ptr::null_mut()));
+    let _restore = Restore(context);
+
+    if context.is_null() {
+        return Err("the world can only be accessed while a script system is running".into());
+    }
+
+    // SAFETY: `enter` only sets the pointer for as long as the context it points to is
+    // borrowed, and `f` can't keep the reference because it has to work for any lifetime.
+    let context = unsafe { &mut *(context as *mut ScriptContext<'_>) };
+    f(context)
+}
@@ -0,0 +1,313 @@
+use crate::resource::set_typed;
+use crate::{
+    mismatch, ComponentAccess, ComponentTable, ReflectError, ResourceTable, ResourceTableError,
+};
+use rhai::{Dynamic, EvalAltResult, Map};
+use specs::prelude::*;
+use specs::shred::{Fetch, FetchMut, MetaTable};
+use specs::storage::AnyStorage;
+use specs::world::{EntitiesRes, Index};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A component defined at runtime, by `register_dynamic_component` or by a script
+/// calling `register_component("Health", #{hp: 100, max: 100})`.
+///
+/// It has every field of its storage's schema, and each field keeps the type of the
+/// schema's value.
+#[derive(Clone, Debug)]
+pub struct DynamicComponent {
+    fields: Map,
+}
+
+impl DynamicComponent {
+    /// every field by name
+    pub fn values(&self) -> &Map {
+        &self.fields
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Dynamic> {
+        self.fields.get(name)
+    }
+
+    /// the field `name`, if it holds a `T`
+    pub fn value<T: Any + Clone>(&self, name: &str) -> Option<T> {
+        self.fields.get(name)?.clone().try_cast()
+    }
+
+    /// set the field `name` to a value of the same type
+    pub fn set(&mut self, component: &str, name: &str, value: Dynamic) -> Result<(), ReflectError> {
+        set_typed(component, &mut self.fields, name, value)
+    }
+}
+
+/// The storage of a `DynamicComponent`, a resource of its own with a dynamic id.
+#[derive(Debug)]
+pub struct DynamicStorage {
+    name: String,
+    schema: Map,
+    components: HashMap<Index, (Entity, DynamicComponent)>,
+    /// entities deleted since the storage was last changed, see `DynamicCleanup`
+    deleted: Arc<Mutex<Vec<Entity>>>,
+}
+
+impl DynamicStorage {
+    /// a storage for components with the fields of `schema`, which also holds their
+    /// default values
+    pub fn new(name: &str, schema: Map) -> Self {
+        DynamicStorage {
+            name: name.to_owned(),
+            schema,
+            components: HashMap::new(),
+            deleted: Arc::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> &Map {
+        &self.schema
+    }
+
+    /// a component with the fields of the schema, overridden by the ones in `fields`
+    pub fn build(&self, fields: Map) -> Result<DynamicComponent, ReflectError> {
+        let mut component = DynamicComponent {
+            fields: self.schema.clone(),
+        };
+        for (name, value) in fields {
+            component.set(&self.name, &name, value)?;
+        }
+
+        Ok(component)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&DynamicComponent> {
+        if self.deleted().contains(&entity) {
+            return None;
+        }
+        match self.components.get(&entity.id()) {
+            Some((owner, component)) if *owner == entity => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut DynamicComponent> {
+        self.sweep();
+        match self.components.get_mut(&entity.id()) {
+            Some((owner, component)) if *owner == entity => Some(component),
+            _ => None,
+        }
+    }
+
+    /// insert or replace the entity's component, returning the old one
+    pub fn insert(
+        &mut self,
+        entity: Entity,
+        component: DynamicComponent,
+    ) -> Option<DynamicComponent> {
+        let old = self.remove(entity);
+        self.components.insert(entity.id(), (entity, component));
+        old
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<DynamicComponent> {
+        self.sweep();
+        self.get(entity)?;
+        self.components
+            .remove(&entity.id())
+            .map(|(_, component)| component)
+    }
+
+    /// every entity with the component. Components of deleted entities are removed
+    /// when the world is maintained.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &DynamicComponent)> {
+        let deleted = self.deleted().clone();
+        self.components
+            .values()
+            .filter(move |(entity, _)| !deleted.contains(entity))
+            .map(|(entity, component)| (*entity, component))
+    }
+
+    fn deleted(&self) -> MutexGuard<'_, Vec<Entity>> {
+        self.deleted.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// drop the components of the entities deleted so far
+    fn sweep(&mut self) {
+        let deleted = std::mem::take(&mut *self.deleted());
+        for entity in deleted {
+            if let Some((owner, _)) = self.components.get(&entity.id()) {
+                if *owner == entity {
+                    self.components.remove(&entity.id());
+                }
+            }
+        }
+    }
+
+    fn component(&self, value: Dynamic) -> Result<DynamicComponent, Box<EvalAltResult>> {
+        let error = mismatch::<Map>(&value);
+        let fields = value.try_cast::<Map>().ok_or(error)?;
+        Ok(self.build(fields)?)
+    }
+}
+
+/// Tells every `DynamicStorage` about the entities deleted when the world is
+/// maintained.
+///
+/// specs only hands deleted entities to storages it knows by their rust type, so this
+/// stands in for all of them. A storage drops the components the next time it is
+/// changed, and leaves them out until then.
+#[derive(Default)]
+struct DynamicCleanup {
+    storages: Vec<Arc<Mutex<Vec<Entity>>>>,
+}
+
+impl AnyStorage for DynamicCleanup {
+    fn drop(&mut self, entities: &[Entity]) {
+        for deleted in &self.storages {
+            deleted
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend_from_slice(entities);
+        }
+    }
+}
+
+/// `ComponentAccess` for a `DynamicStorage`.
+struct DynamicAccess {
+    id: ResourceId,
+}
+
+impl DynamicAccess {
+    fn storage(storage: &dyn Resource) -> &DynamicStorage {
+        storage
+            .downcast_ref::<DynamicStorage>()
+            .expect("bug: component storage has the wrong type")
+    }
+}
+
+impl ComponentAccess for DynamicAccess {
+    fn storage_id(&self) -> ResourceId {
+        self.id.clone()
+    }
+
+    fn mask(&self, storage: &dyn Resource, entities: &Fetch<EntitiesRes>) -> BitSet {
+        Self::storage(storage)
+            .iter()
+            .filter(|(entity, _)| entities.is_alive(*entity))
+            .map(|(entity, _)| entity.id())
+            .collect()
+    }
+
+    fn get(
+        &self,
+        storage: &dyn Resource,
+        _entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+    ) -> Option<Dynamic> {
+        Self::storage(storage)
+            .get(entity)
+            .map(|component| component.values().clone().into())
+    }
+
+    fn set(
+        &self,
+        storage: &mut dyn Resource,
+        entities: &Fetch<EntitiesRes>,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>> {
+        if !entities.is_alive(entity) {
+            return Err(format!("entity {} is dead", entity.id()).into());
+        }
+        let storage = storage
+            .downcast_mut::<DynamicStorage>()
+            .expect("bug: component storage has the wrong type");
+
+        let component = storage.component(value)?;
+        storage.insert(entity, component);
+        Ok(())
+    }
+
+    fn insert_lazy(
+        &self,
+        lazy: &LazyUpdate,
+        entity: Entity,
+        value: Dynamic,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let error = mismatch::<Map>(&value);
+        let fields = value.try_cast::<Map>().ok_or(error)?;
+
+        let id = self.id.clone();
+        lazy.exec_mut(move |world| {
+            let mut storage = world
+                .try_fetch_mut_by_id::<DynamicStorage>(id)
+                .expect("bug: dynamic component storage was removed");
+            match storage.build(fields) {
+                Ok(component) => {
+                    storage.insert(entity, component);
+                }
+                Err(err) => println!("{}", err),
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Insert a `DynamicStorage` called `name` into the world and make it available to
+/// scripts, like a component registered with `WorldHelper::register_scriptable`.
+pub fn register_dynamic_component(
+    world: &mut World,
+    name: &str,
+    schema: Map,
+) -> Result<ResourceId, ResourceTableError> {
+    let id = world
+        .entry::<ResourceTable>()
+        .or_insert_with(ResourceTable::new)
+        .register_dynamic::<DynamicStorage>(name)?;
+    let storage = DynamicStorage::new(name, schema);
+    world
+        .entry::<DynamicCleanup>()
+        .or_insert_with(DynamicCleanup::default)
+        .storages
+        .push(storage.deleted.clone());
+    world.insert_by_id(id.clone(), storage);
+    world
+        .entry::<MetaTable<dyn AnyStorage>>()
+        .or_insert_with(Default::default);
+    world
+        .fetch_mut::<MetaTable<dyn AnyStorage>>()
+        .register(&*world.fetch::<DynamicCleanup>());
+    world
+        .entry::<ComponentTable>()
+        .or_insert_with(ComponentTable::new)
+        .register_access(name, Box::new(DynamicAccess { id: id.clone() }));
+
+    Ok(id)
+}
+
+/// the storage of the dynamic component called `name`, `None` if there's no such
+/// component or `name` is another kind of resource
+pub fn fetch_dynamic_storage<'a>(
+    world: &'a World,
+    name: &str,
+) -> Option<Fetch<'a, DynamicStorage>> {
+    let id = world
+        .try_fetch::<ResourceTable>()?
+        .get_as::<DynamicStorage>(name)?;
+    world.try_fetch_by_id(id)
+}
+
+/// the storage of the dynamic component called `name`, to change it
+pub fn fetch_dynamic_storage_mut<'a>(
+    world: &'a World,
+    name: &str,
+) -> Option<FetchMut<'a, DynamicStorage>> {
+    let id = world
+        .try_fetch::<ResourceTable>()?
+        .get_as::<DynamicStorage>(name)?;
+    world.try_fetch_mut_by_id(id)
+}
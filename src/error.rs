@@ -0,0 +1,168 @@
+use crate::limits;
+use rhai::{EvalAltResult, ParseError};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while loading or running a script.
+#[derive(Debug)]
+pub enum ScriptError {
+    /// the script file couldn't be read
+    Io {
+        script: String,
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// the script failed to compile
+    Parse {
+        script: String,
+        message: String,
+        position: rhai::Position,
+    },
+    /// the script raised an error while running
+    Runtime {
+        script: String,
+        message: String,
+        position: rhai::Position,
+    },
+    /// the script doesn't define a function it was expected to
+    MissingFunction { script: String, function: String },
+    /// the script went over one of its `ScriptLimits`, like "operations" or "call depth"
+    LimitExceeded {
+        script: String,
+        limit: String,
+        position: rhai::Position,
+    },
+}
+
+impl ScriptError {
+    pub(crate) fn parse(script: &str, error: ParseError) -> Self {
+        ScriptError::Parse {
+            script: script.to_owned(),
+            message: error.0.to_string(),
+            position: error.1,
+        }
+    }
+
+    pub(crate) fn runtime(script: &str, error: EvalAltResult) -> Self {
+        if let Some((limit, position)) = exceeded_limit(&error) {
+            return ScriptError::LimitExceeded {
+                script: script.to_owned(),
+                limit: limit.to_owned(),
+                position,
+            };
+        }
+
+        // errors raised inside the called function carry the useful position
+        let error = match error {
+            EvalAltResult::ErrorInFunctionCall(_, _, inner, position) if position.is_none() => {
+                *inner
+            }
+            error => error,
+        };
+
+        match error {
+            EvalAltResult::ErrorParsing(error, position) => ScriptError::Parse {
+                script: script.to_owned(),
+                message: error.to_string(),
+                position,
+            },
+            mut error => {
+                // keep the position out of the message, it's reported separately
+                let position = error.take_position();
+                ScriptError::Runtime {
+                    script: script.to_owned(),
+                    message: error.to_string(),
+                    position,
+                }
+            }
+        }
+    }
+
+    pub(crate) fn limit_exceeded(script: &str, limit: &str) -> Self {
+        ScriptError::LimitExceeded {
+            script: script.to_owned(),
+            limit: limit.to_owned(),
+            position: rhai::Position::NONE,
+        }
+    }
+
+    /// whether the script went over a limit, which disables it
+    pub fn is_limit_exceeded(&self) -> bool {
+        matches!(self, ScriptError::LimitExceeded { .. })
+    }
+
+    /// the name of the script that failed
+    pub fn script(&self) -> &str {
+        match self {
+            ScriptError::Io { script, .. }
+            | ScriptError::Parse { script, .. }
+            | ScriptError::Runtime { script, .. }
+            | ScriptError::MissingFunction { script, .. }
+            | ScriptError::LimitExceeded { script, .. } => script,
+        }
+    }
+
+    /// where in the script the error happened, if known
+    pub fn position(&self) -> Option<rhai::Position> {
+        match self {
+            ScriptError::Parse { position, .. }
+            | ScriptError::Runtime { position, .. }
+            | ScriptError::LimitExceeded { position, .. }
+                if !position.is_none() =>
+            {
+                Some(*position)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io {
+                script,
+                path,
+                error,
+            } => write!(f, "{}: cannot read {}: {}", script, path.display(), error)?,
+            ScriptError::Parse {
+                script, message, ..
+            } => write!(f, "{}: syntax error: {}", script, message)?,
+            ScriptError::Runtime {
+                script, message, ..
+            } => write!(f, "{}: {}", script, message)?,
+            ScriptError::MissingFunction { script, function } => {
+                write!(f, "{}: function `{}` is not defined", script, function)?
+            }
+            ScriptError::LimitExceeded { script, limit, .. } => {
+                write!(f, "{}: went over its {} limit", script, limit)?
+            }
+        }
+
+        match self.position() {
+            Some(position) => write!(f, " ({})", position),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the limit behind `error` and where it was hit, looking through the calls it
+/// happened in
+fn exceeded_limit(error: &EvalAltResult) -> Option<(&'static str, rhai::Position)> {
+    match error {
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, position)
+        | EvalAltResult::ErrorInModule(_, inner, position) => exceeded_limit(inner)
+            .map(|(limit, inner)| (limit, if inner.is_none() { *position } else { inner })),
+        error => limits::exceeded_limit(error).map(|limit| (limit, error.position())),
+    }
+}
+
+impl Error for ScriptError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScriptError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
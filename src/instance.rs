@@ -0,0 +1,50 @@
+use rhai::Scope;
+use specs::prelude::*;
+use specs::Component;
+
+/// Attaches a script to an entity, with the entity's own copy of the script's variables.
+///
+/// The entity is updated by the `ScriptSystem` of the script, which calls
+/// `update(entity, delta)` with the instance's scope. The scope starts out as the
+/// result of the script's top level statements, and starts over when the script is
+/// reloaded.
+#[derive(Component, Debug)]
+pub struct ScriptInstance {
+    script: String,
+    scope: Scope<'static>,
+    /// the version of the script the scope was made by, `None` before the first update
+    version: Option<u64>,
+}
+
+impl ScriptInstance {
+    /// attach the script `script`
+    pub fn new(script: &str) -> Self {
+        ScriptInstance {
+            script: script.to_owned(),
+            scope: Scope::new(),
+            version: None,
+        }
+    }
+
+    pub fn script(&self) -> &str {
+        &self.script
+    }
+
+    /// the entity's variables
+    pub fn scope(&self) -> &Scope<'static> {
+        &self.scope
+    }
+
+    /// the scope to run the given version of the script in, if it has been set up
+    pub(crate) fn scope_for(&mut self, version: u64) -> Option<&mut Scope<'static>> {
+        match self.version {
+            Some(current) if current == version => Some(&mut self.scope),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_scope(&mut self, scope: Scope<'static>, version: u64) {
+        self.scope = scope;
+        self.version = Some(version);
+    }
+}
@@ -0,0 +1,1136 @@
+use rhai::{Dynamic, Engine, Map, Scope, AST, INT};
+use specs::prelude::*;
+use specs::shred::cell::{Ref, RefMut};
+use specs::shred::{CastFrom, DynamicSystemData, Fetch, MetaTable};
+use specs::world::EntitiesRes;
+use specs::Component;
+use specs::{Read, World, WorldExt};
+use std::any::type_name;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+// lets `#[derive(Scriptable)]` refer to this crate as `::rhai_specs_test` from inside it
+extern crate self as rhai_specs_test;
+
+pub use rhai;
+pub use rhai_specs_test_derive::{script_methods, Scriptable};
+
+mod api;
+mod binding;
+mod component;
+mod context;
+mod dynamic;
+mod error;
+mod instance;
+mod lifecycle;
+mod limits;
+mod reflect;
+mod registry;
+mod resource;
+mod source;
+mod stack;
+mod system;
+mod time;
+mod value;
+
+pub use api::register_world_api;
+pub use binding::{BindingError, BindingReport, ComponentScript, ScriptBinding};
+pub use component::{ComponentAccess, ComponentTable};
+pub use dynamic::{
+    fetch_dynamic_storage, fetch_dynamic_storage_mut, register_dynamic_component, DynamicComponent,
+    DynamicStorage,
+};
+pub use error::ScriptError;
+pub use instance::ScriptInstance;
+pub use lifecycle::LifecycleSystem;
+pub use limits::ScriptLimits;
+pub use reflect::{ReflectError, ScriptMethods};
+pub use registry::{ScriptLibrary, ScriptRegistry};
+pub use resource::{
+    define_resource, fetch_resource, fetch_resource_mut, ResourceTable, ResourceTableError,
+    ScriptableResource, ScriptingResAccessor, ScriptingResData,
+};
+pub use source::{
+    DirectorySource, EmbeddedSource, MemorySource, ScriptSource, ScriptText, SCRIPTS_DIR_VAR,
+};
+pub use stack::{ScriptStack, ScriptStackSystem};
+pub use system::ScriptSystem;
+pub use time::{Time, TimeSystem};
+pub use value::{mismatch, Field, Method, ScriptValue};
+
+#[cfg(test)]
+mod tests;
+
+pub struct Dependencies {
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    /// the names that were declared but aren't in the resource table
+    unknown: Vec<String>,
+}
+
+impl Dependencies {
+    pub fn new(reads: Vec<ResourceId>, writes: Vec<ResourceId>) -> Self {
+        Dependencies {
+            reads,
+            writes,
+            unknown: Vec::new(),
+        }
+    }
+
+    /// resolve the names a script declares in `reads()`/`writes()` (or the
+    /// `READS`/`WRITES` constants) through the resource table. Each resource is
+    /// listed once, and a resource that's written isn't read as well. Unknown names
+    /// are left out and kept in `unknown`, using them fails when the script runs.
+    pub fn from_script(script: &mut Script, engine: &Engine, table: &ResourceTable) -> Self {
+        let mut unknown = Vec::new();
+        let mut resolve = |function, constant| {
+            let mut ids = Vec::new();
+            for name in script.declared_names(engine, function, constant) {
+                match table.get(&name) {
+                    Some(id) if !ids.contains(&id) => ids.push(id),
+                    Some(_) => {}
+                    None if !unknown.contains(&name) => unknown.push(name),
+                    None => {}
+                }
+            }
+            ids
+        };
+        let writes = resolve("writes", "WRITES");
+        let mut reads = resolve("reads", "READS");
+        reads.retain(|id| !writes.contains(id));
+
+        Dependencies {
+            reads,
+            writes,
+            unknown,
+        }
+    }
+
+    /// the declared names that aren't in the resource table
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+}
+
+impl Accessor for Dependencies {
+    fn try_new() -> Option<Self> {
+        // there's no default for this
+        None
+    }
+
+    fn reads(&self) -> Vec<ResourceId> {
+        let mut reads = self.reads.clone();
+        reads.push(ResourceId::new::<ReflectionTable>());
+        reads.push(ResourceId::new::<Engine>());
+        reads.push(ResourceId::new::<ScriptLibrary>());
+        reads.push(ResourceId::new::<EntitiesRes>());
+        reads.push(ResourceId::new::<ComponentTable>());
+        reads.push(ResourceId::new::<ResourceTable>());
+        reads.push(ResourceId::new::<LazyUpdate>());
+        // scripts writing the time get it from their writes
+        let time = ResourceId::new::<Time>();
+        if !self.writes.contains(&time) {
+            reads.push(time);
+        }
+
+        reads
+    }
+
+    fn writes(&self) -> Vec<ResourceId> {
+        self.writes.clone()
+    }
+}
+
+pub type ReflectionTable = MetaTable<dyn ScriptableComponent>;
+
+// gets data
+pub struct ScriptSystemData<'a> {
+    pub meta_table: Read<'a, ReflectionTable>,
+    pub engine: ReadExpect<'a, Engine>,
+    pub library: Read<'a, ScriptLibrary>,
+    pub entities: Fetch<'a, EntitiesRes>,
+    pub components: Read<'a, ComponentTable>,
+    pub resources: Read<'a, ResourceTable>,
+    pub lazy: Read<'a, LazyUpdate>,
+    /// the time, unless the script declared it writes it
+    pub time: Option<Fetch<'a, Time>>,
+    pub reads: Vec<Ref<'a, Box<dyn Resource + 'static>>>,
+    pub writes: Vec<RefMut<'a, Box<dyn Resource + 'static>>>,
+}
+
+impl<'a> ScriptSystemData<'a> {
+    /// the resource with the id `id`, if `dependencies` made it fetch it
+    pub(crate) fn fetched(
+        &self,
+        dependencies: &Dependencies,
+        id: &ResourceId,
+    ) -> Option<&(dyn Resource + 'static)> {
+        let reads = dependencies.reads.iter().zip(self.reads.iter());
+        let writes = dependencies.writes.iter().zip(self.writes.iter());
+        reads
+            .map(|(id, resource)| (id, &***resource))
+            .chain(writes.map(|(id, resource)| (id, &***resource)))
+            .find(|(fetched, _)| *fetched == id)
+            .map(|(_, resource)| resource)
+    }
+
+    /// the time, if it's in the world
+    pub(crate) fn time(&self, dependencies: &Dependencies) -> Option<&Time> {
+        match &self.time {
+            Some(time) => Some(time),
+            None => self
+                .fetched(dependencies, &ResourceId::new::<Time>())?
+                .downcast_ref(),
+        }
+    }
+}
+
+impl<'a> DynamicSystemData<'a> for ScriptSystemData<'a> {
+    type Accessor = Dependencies;
+
+    fn setup(_accessor: &Dependencies, res: &mut World) {
+        res.entry::<ReflectionTable>()
+            .or_insert_with(ReflectionTable::new);
+        res.register::<ScriptInstance>();
+        let limits = res.try_fetch::<ScriptLimits>().map(|limits| *limits);
+        let mut engine = res.entry::<Engine>().or_insert_with(Engine::new);
+        register_world_api(&mut engine);
+        if let Some(limits) = limits {
+            limits.apply(&mut engine);
+        }
+        drop(engine);
+        res.entry::<ComponentTable>()
+            .or_insert_with(ComponentTable::new);
+        res.entry::<ResourceTable>()
+            .or_insert_with(ResourceTable::new);
+        res.entry::<ScriptLibrary>()
+            .or_insert_with(ScriptLibrary::default);
+    }
+
+    fn fetch(access: &Dependencies, res: &'a World) -> Self {
+        let reads = access
+            .reads
+            .iter()
+            .map(|id| {
+                res.try_fetch_internal(id.clone())
+                    .expect("bug: the requested resource does not exist")
+                    .borrow()
+            })
+            .collect();
+        let writes = access
+            .writes
+            .iter()
+            .map(|id| {
+                res.try_fetch_internal(id.clone())
+                    .expect("bug: the requested resource does not exist")
+                    .borrow_mut()
+            })
+            .collect();
+
+        ScriptSystemData {
+            meta_table: SystemData::fetch(res),
+            engine: SystemData::fetch(res),
+            library: SystemData::fetch(res),
+            entities: res.fetch(),
+            components: SystemData::fetch(res),
+            resources: SystemData::fetch(res),
+            lazy: SystemData::fetch(res),
+            time: if access.writes.contains(&ResourceId::new::<Time>()) {
+                None
+            } else {
+                res.try_fetch()
+            },
+            reads,
+            writes,
+        }
+    }
+}
+
+/// trait that all components that scripts can access should implement,
+/// usually through `#[derive(Scriptable)]`
+pub trait ScriptableComponent {
+    fn setup(&mut self, name: &str) {
+        println!("setting up: {}", name)
+    }
+
+    /// the name scripts know the component by
+    fn type_name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+
+    /// the name scripts know the component by, which the world registers it under.
+    /// Defaults to the type name without its module path.
+    fn name() -> &'static str
+    where
+        Self: Sized,
+    {
+        short_type_name::<Self>()
+    }
+
+    /// the fields scripts can see
+    fn fields(&self) -> &'static [Field] {
+        &[]
+    }
+
+    /// the value of the field `name`
+    fn get_field(&self, name: &str) -> Result<Dynamic, ReflectError> {
+        Err(ReflectError::UnknownField {
+            component: self.type_name().to_owned(),
+            field: name.to_owned(),
+        })
+    }
+
+    /// set the field `name`, unless it is read only
+    fn set_field(&mut self, name: &str, value: Dynamic) -> Result<(), ReflectError> {
+        let _ = value;
+        Err(ReflectError::UnknownField {
+            component: self.type_name().to_owned(),
+            field: name.to_owned(),
+        })
+    }
+
+    /// the methods scripts can call
+    fn methods(&self) -> &'static [Method] {
+        &[]
+    }
+
+    /// call a method taking `&self`, calling one that takes `&mut self` is an error
+    fn call_method(&self, name: &str, args: &[Dynamic]) -> Result<Dynamic, ReflectError> {
+        let _ = args;
+        Err(ReflectError::UnknownMethod {
+            component: self.type_name().to_owned(),
+            method: name.to_owned(),
+        })
+    }
+
+    /// call any method
+    fn call_method_mut(&mut self, name: &str, args: &[Dynamic]) -> Result<Dynamic, ReflectError> {
+        self.call_method(name, args)
+    }
+
+    /// every visible field by name
+    fn to_map(&self) -> Map {
+        self.fields()
+            .iter()
+            .filter_map(|field| Some((field.name.into(), self.get_field(field.name).ok()?)))
+            .collect()
+    }
+
+    /// set every field in `map`, stopping at the first one that can't be set
+    fn set_fields(&mut self, map: Map) -> Result<(), ReflectError> {
+        map.into_iter()
+            .try_for_each(|(name, value)| self.set_field(&name, value))
+    }
+
+    /// build the component from a map holding every visible field. Hidden fields get
+    /// their default value.
+    fn from_map(map: Map) -> Result<Self, ReflectError>
+    where
+        Self: Sized;
+
+    /// the name of the script bound to the component. It's the only script the component
+    /// gets, scripts declaring the component themselves are left unassigned.
+    fn script() -> Option<&'static str>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// the category whose fallback script is used when the component has no script
+    /// of its own, see `WorldHelper::set_category_script`
+    fn category() -> Option<&'static str>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// register the component's rhai type, constructor and field getters/setters
+    fn register_rhai(engine: &mut Engine)
+    where
+        Self: Sized;
+}
+
+/// dummy component for testing
+#[derive(Scriptable, Clone, Debug)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+// flagged so scripts can hook into its lifecycle
+impl Component for Position {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+// necessary for `MetaTable`
+unsafe impl<T> CastFrom<T> for dyn ScriptableComponent
+where
+    T: ScriptableComponent + 'static,
+{
+    fn cast(t: &T) -> &Self {
+        t
+    }
+
+    fn cast_mut(t: &mut T) -> &mut Self {
+        t
+    }
+}
+
+pub struct WorldHelper {
+    unassigned_scripts: HashMap<String, Script>,
+    default_script: Option<Script>,
+    category_scripts: HashMap<String, Script>,
+    report: BindingReport,
+    world: World,
+}
+
+impl WorldHelper {
+    /// wrap `world`, adding the world api to its engine so scripts can be compiled
+    /// before any script system is set up
+    pub fn new(mut world: World) -> Self {
+        register_world_api(&mut world.entry::<Engine>().or_insert_with(Engine::new));
+        WorldHelper {
+            unassigned_scripts: HashMap::new(),
+            default_script: None,
+            category_scripts: HashMap::new(),
+            report: BindingReport::default(),
+            world,
+        }
+    }
+
+    /// add scripts waiting to be bound to a component by `register_scriptable`
+    pub fn add_scripts(&mut self, scripts: impl IntoIterator<Item = Script>) {
+        for script in scripts {
+            self.unassigned_scripts
+                .insert(script.name().to_owned(), script);
+        }
+    }
+
+    /// the script used by components that have no script and no category fallback
+    pub fn set_default_script(&mut self, script: Script) {
+        self.default_script = Some(script);
+    }
+
+    /// the script used by components of `category` that have no script
+    pub fn set_category_script(&mut self, category: &str, script: Script) {
+        self.category_scripts.insert(category.to_owned(), script);
+    }
+
+    /// the scripts bound to the component `S`, in the order they run. Panics if `S`
+    /// wasn't registered with `register_scriptable`.
+    pub fn scripts_for<S: 'static>(&self) -> Fetch<'_, ScriptStack<S>> {
+        self.world.fetch::<ScriptStack<S>>()
+    }
+
+    /// a system running the update phases of the scripts bound to `S`, see
+    /// `ScriptStackSystem`
+    pub fn stack_system<S: Component>(&self) -> ScriptStackSystem<S> {
+        ScriptStackSystem::new(&self.world)
+    }
+
+    /// let scripts ask which entities' `S` changed since they last ran, with
+    /// `query_changed`. `S` is registered with `register_scriptable` first.
+    pub fn track_changes<S>(&mut self)
+    where
+        S: ScriptableComponent + ScriptValue + Component + Send + Sync,
+        S::Storage: Tracked,
+    {
+        self.world
+            .entry::<ComponentTable>()
+            .or_insert_with(ComponentTable::new)
+            .register_tracked::<S>(S::name());
+    }
+
+    /// a system calling the lifecycle hooks of the enabled scripts bound to `S`, see
+    /// `LifecycleSystem`. `None` if none of them defines a hook.
+    pub fn lifecycle_system<S>(&self) -> Option<LifecycleSystem<S>>
+    where
+        S: Component,
+        S::Storage: Tracked,
+    {
+        let has_hooks = self
+            .scripts_for::<S>()
+            .enabled()
+            .any(LifecycleSystem::<S>::has_hooks);
+        has_hooks.then(|| LifecycleSystem::new(&self.world))
+    }
+
+    /// enable or disable the script `script` of the component `S`, returning whether
+    /// it is bound to the component. Systems already running the scripts see the change.
+    pub fn set_script_enabled<S: 'static>(&mut self, script: &str, enabled: bool) -> bool {
+        self.world
+            .try_fetch_mut::<ScriptStack<S>>()
+            .is_some_and(|mut stack| stack.set_enabled(script, enabled))
+    }
+
+    /// move the script `script` of the component `S` to `priority`, returning whether
+    /// it is bound to the component. Scripts with equal priorities keep their order.
+    pub fn set_script_priority<S: 'static>(&mut self, script: &str, priority: INT) -> bool {
+        self.world
+            .try_fetch_mut::<ScriptStack<S>>()
+            .is_some_and(|mut stack| stack.set_priority(script, priority))
+    }
+
+    /// make `limits` the global limits of the world's scripts, see `ScriptLimits`. They
+    /// are kept in the world, and applied again whenever a system is set up.
+    pub fn set_limits(&mut self, limits: ScriptLimits) {
+        self.world.insert(limits);
+        limits.apply(&mut self.world.entry::<Engine>().or_insert_with(Engine::new));
+    }
+
+    /// which script every registered component got, and which ones fell back
+    pub fn report(&self) -> &BindingReport {
+        &self.report
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// insert a resource scripts can reach with `resource("Name")` and
+    /// `resource_mut("Name")`, registering it in the resource and reflection tables
+    pub fn insert_scriptable_resource<R>(&mut self, resource: R)
+    where
+        R: ScriptableComponent + Resource,
+    {
+        let name = R::name();
+        R::register_rhai(&mut self.world.entry::<Engine>().or_insert_with(Engine::new));
+        self.world
+            .entry::<ReflectionTable>()
+            .or_insert_with(ReflectionTable::new)
+            .register(&resource);
+        self.world
+            .entry::<ResourceTable>()
+            .or_insert_with(ResourceTable::new)
+            .register::<R>(name);
+        self.world.insert(resource);
+    }
+
+    /// define a `ScriptableResource` called `name` with the given fields, see
+    /// `define_resource`
+    pub fn define_resource(&mut self, name: &str, fields: Map) -> Result<(), ResourceTableError> {
+        define_resource(&mut self.world, name, fields).map(|_| ())
+    }
+
+    /// register a `DynamicComponent` called `name`, whose fields and their defaults
+    /// are the ones in `schema`, see `register_dynamic_component`
+    pub fn register_component(
+        &mut self,
+        name: &str,
+        schema: Map,
+    ) -> Result<(), ResourceTableError> {
+        register_dynamic_component(&mut self.world, name, schema).map(|_| ())
+    }
+
+    /// register a component with the world and its rhai bindings with the engine, and
+    /// bind it to a script.
+    ///
+    /// The script named by `ScriptableComponent::script` wins if there is one, otherwise
+    /// the scripts are the ones declaring the component with `fn component()` or
+    /// `const COMPONENT`, ordered by the priority they declare. Without any, the
+    /// component gets a fallback. The component is registered even if binding it fails.
+    pub fn register_scriptable<S>(&mut self) -> Result<(), BindingError>
+    where
+        S: ScriptableComponent + ScriptValue + Component + Send + Sync,
+        S::Storage: Default,
+    {
+        self.register::<S>(S::script())
+    }
+
+    /// like `register_scriptable`, but bind the component to the script `script` alone
+    pub fn register_scriptable_with<S>(&mut self, script: &str) -> Result<(), BindingError>
+    where
+        S: ScriptableComponent + ScriptValue + Component + Send + Sync,
+        S::Storage: Default,
+    {
+        self.register::<S>(Some(script))
+    }
+
+    fn register<S>(&mut self, script: Option<&str>) -> Result<(), BindingError>
+    where
+        S: ScriptableComponent + ScriptValue + Component + Send + Sync,
+        S::Storage: Default,
+    {
+        let name = S::name();
+        self.world.register::<S>();
+        self.world.insert(ScriptStack::<S>::default());
+        S::register_rhai(&mut self.world.entry::<Engine>().or_insert_with(Engine::new));
+        self.world
+            .entry::<ResourceTable>()
+            .or_insert_with(ResourceTable::new)
+            .register_component::<S>(name);
+        self.world
+            .entry::<ComponentTable>()
+            .or_insert_with(ComponentTable::new)
+            .register::<S>(name);
+
+        let scripts = self.take_scripts(name, script)?;
+        let category = S::category();
+        let fallback = category
+            .and_then(|category| self.category_scripts.get(category))
+            .or(self.default_script.as_ref());
+        if let Some(fallback) = fallback.filter(|_| scripts.is_empty()) {
+            stackable(name, fallback)?;
+        }
+        let (scripts, binding) = if !scripts.is_empty() {
+            let binding = ScriptBinding::Bound {
+                scripts: scripts
+                    .iter()
+                    .map(|bound| bound.script().name().to_owned())
+                    .collect(),
+            };
+            (scripts, binding)
+        } else if let Some((category, script)) =
+            category.and_then(|category| Some((category, self.category_scripts.get(category)?)))
+        {
+            let binding = ScriptBinding::Category {
+                category: category.to_owned(),
+                script: script.name().to_owned(),
+            };
+            (vec![ComponentScript::new(script.clone(), 0)], binding)
+        } else if let Some(script) = &self.default_script {
+            let binding = ScriptBinding::Default {
+                script: script.name().to_owned(),
+            };
+            (vec![ComponentScript::new(script.clone(), 0)], binding)
+        } else {
+            (Vec::new(), ScriptBinding::Unbound)
+        };
+
+        match &binding {
+            ScriptBinding::Bound { .. } => {}
+            ScriptBinding::Unbound => println!("no script for {}", name),
+            fallback => println!("no script for {}, falling back to {}", name, fallback),
+        }
+        self.world.insert(ScriptStack::<S>::new(scripts));
+        self.report.record(name, binding);
+        Ok(())
+    }
+
+    /// remove the scripts bound to `component` from the unassigned scripts, ordered
+    /// by their priority. An explicitly bound script is the only one taken.
+    fn take_scripts(
+        &mut self,
+        component: &str,
+        explicit: Option<&str>,
+    ) -> Result<Vec<ComponentScript>, BindingError> {
+        let engine = self.world.fetch::<Engine>();
+        if let Some(explicit) = explicit {
+            let script = self.unassigned_scripts.get(explicit).ok_or_else(|| {
+                BindingError::MissingScript {
+                    component: component.to_owned(),
+                    script: explicit.to_owned(),
+                }
+            })?;
+            stackable(component, script)?;
+            let mut script = self
+                .unassigned_scripts
+                .remove(explicit)
+                .expect("bug: explicit script is missing");
+            let priority = script.declared_priority(&engine);
+            return Ok(vec![ComponentScript::new(script, priority)]);
+        }
+
+        let mut bound: Vec<(INT, String)> = self
+            .unassigned_scripts
+            .iter_mut()
+            .filter_map(|(name, script)| {
+                let declared = script.declared_component(&engine)?;
+                if declared != component {
+                    return None;
+                }
+                Some((script.declared_priority(&engine), name.clone()))
+            })
+            .collect();
+        bound.sort();
+
+        // scripts with the same priority could run in either order
+        for window in bound.windows(2) {
+            if window[0].0 == window[1].0 {
+                let priority = window[0].0;
+                return Err(BindingError::Ambiguous {
+                    component: component.to_owned(),
+                    scripts: bound
+                        .iter()
+                        .filter(|(other, _)| *other == priority)
+                        .map(|(_, name)| name.clone())
+                        .collect(),
+                });
+            }
+        }
+
+        for (_, name) in &bound {
+            stackable(component, &self.unassigned_scripts[name])?;
+        }
+
+        Ok(bound
+            .into_iter()
+            .filter_map(|(priority, name)| {
+                let script = self.unassigned_scripts.remove(&name)?;
+                Some(ComponentScript::new(script, priority))
+            })
+            .collect())
+    }
+}
+
+/// whether `script` can run in the `ScriptStack` of `component`. Scripts updating
+/// each entity on their own are run by a `ScriptSystem` instead.
+fn stackable(component: &str, script: &Script) -> Result<(), BindingError> {
+    if script.is_per_entity() {
+        return Err(BindingError::PerEntity {
+            component: component.to_owned(),
+            script: script.name().to_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// the type name without its module path, e.g. `Position`
+fn short_type_name<T>() -> &'static str {
+    let name = type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// load a script from a file path. It's named the way a `DirectorySource` of `root`
+/// names its scripts, so a `ScriptRegistry` watching `root` reloads the script. A script
+/// outside `root` is named by its file name.
+pub fn load_script(root: &Path, path: &Path, engine: &Engine) -> Result<Script, ScriptError> {
+    ScriptText::from_file(&source::script_name(root, path), path)?.load(engine)
+}
+
+/// run the update phases of every script. A script that fails is skipped and its error
+/// returned, the others still run. Scripts disabled for going over a limit don't run.
+pub fn tick(scripts: &mut [Script], engine: &Engine) -> Vec<ScriptError> {
+    scripts
+        .iter_mut()
+        .filter_map(|script| script.update(engine).err())
+        .collect()
+}
+
+pub struct ScriptInput<'a> {
+    pub reads: HashMap<&'a str, &'a dyn ScriptableComponent>,
+    pub writes: HashMap<&'a str, &'a mut dyn ScriptableComponent>,
+}
+
+/// the length of a fixed step, unless the script is given another with
+/// `Script::set_fixed_step`
+pub const DEFAULT_FIXED_STEP: f64 = 1.0 / 60.0;
+
+/// the most fixed steps run in one frame, time beyond that is dropped so a slow
+/// frame doesn't make the next one slower
+const MAX_FIXED_STEPS: u32 = 8;
+
+/// the phases of an update, in the order they run. Each is optional.
+const PHASES: [&str; 3] = ["fixed_update", "update", "late_update"];
+
+/// How far a script's clock moved since it last ran.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    /// the seconds since the script last ran
+    pub delta: f64,
+    /// the length of a fixed step
+    pub step: f64,
+    /// how many fixed steps passed
+    pub steps: u32,
+}
+
+/// A loaded script.
+///
+/// Each update runs `fixed_update(step)` once for every fixed step that passed, then
+/// `update(delta)` and `late_update(delta)`. `unload()` is called when the script is
+/// reloaded or removed. Only `load()` has to be defined.
+///
+/// A script can set its own `ScriptLimits` with `fn limits()` or `const LIMITS`. Going
+/// over one disables the script, it doesn't run again until it's reloaded.
+#[derive(Clone, Debug)]
+pub struct Script {
+    name: String,
+    script_ast: AST,
+    scope: Scope<'static>,
+    last_run: Instant,
+    /// the `ScriptLibrary` version this script was compiled from
+    version: u64,
+    fixed_step: f64,
+    /// whether the step was set with `set_fixed_step`, rather than taken from `Time`
+    own_step: bool,
+    /// the time that passed but didn't make a whole fixed step yet
+    accumulator: f64,
+    unloaded: bool,
+    limits: ScriptLimits,
+    disabled: bool,
+}
+
+impl Script {
+    /// run the top level statements of a compiled script and call its `load`. The limits
+    /// the script declares are read first, so its top level statements are held to them.
+    pub fn load(name: &str, ast: AST, engine: &Engine) -> Result<Script, ScriptError> {
+        let mut script = Script {
+            name: name.to_owned(),
+            script_ast: ast,
+            scope: Scope::new(),
+            last_run: Instant::now(),
+            version: 0,
+            fixed_step: DEFAULT_FIXED_STEP,
+            own_step: false,
+            accumulator: 0.0,
+            unloaded: false,
+            limits: ScriptLimits::default(),
+            disabled: false,
+        };
+        script.limits = script.declared_limits(engine)?;
+        script.scope = script.new_scope(engine)?;
+        script.call(engine, "load", ())?;
+        Ok(script)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// whether the script defines a function `name` taking `params` arguments
+    pub fn has_fn(&self, name: &str, params: usize) -> bool {
+        self.script_ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == params)
+    }
+
+    /// the value returned by the function `function`, or held by the constant `constant`
+    /// if the function isn't defined
+    pub fn declared(&mut self, engine: &Engine, function: &str, constant: &str) -> Option<Dynamic> {
+        if self.has_fn(function, 0) {
+            self.call(engine, function, ()).ok()
+        } else {
+            self.scope.get_value::<Dynamic>(constant)
+        }
+    }
+
+    /// names listed by the function `function`, or by the constant `constant` if the
+    /// function isn't defined. Non-string entries are ignored.
+    pub fn declared_names(
+        &mut self,
+        engine: &Engine,
+        function: &str,
+        constant: &str,
+    ) -> Vec<String> {
+        self.declared(engine, function, constant)
+            .and_then(|names| names.into_array().ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|name| name.into_string().ok())
+            .collect()
+    }
+
+    /// the component the script says it's for, with `fn component()` or `const COMPONENT`
+    pub fn declared_component(&mut self, engine: &Engine) -> Option<String> {
+        self.declared(engine, "component", "COMPONENT")
+            .and_then(|name| name.into_string().ok())
+    }
+
+    /// where the script goes among the scripts of its component, with `fn priority()`
+    /// or `const PRIORITY`. Defaults to 0.
+    pub fn declared_priority(&mut self, engine: &Engine) -> INT {
+        self.declared(engine, "priority", "PRIORITY")
+            .and_then(|priority| priority.as_int().ok())
+            .unwrap_or(0)
+    }
+
+    /// the limits the script sets itself with `fn limits()` or `const LIMITS`. They are
+    /// read before the top level statements run, so `fn limits()` only sees the
+    /// script's literal constants.
+    pub fn declared_limits(&self, engine: &Engine) -> Result<ScriptLimits, ScriptError> {
+        let mut scope: Scope = self
+            .script_ast
+            .iter_literal_variables(true, false)
+            .collect();
+        let limits = if self.has_fn("limits", 0) {
+            limits::enter(ScriptLimits::MANIFEST, || {
+                engine.call_fn_raw(
+                    &mut scope,
+                    &self.script_ast,
+                    false,
+                    true,
+                    "limits",
+                    None,
+                    [],
+                )
+            })
+            .map_err(|error| ScriptError::runtime(&self.name, *error))?
+        } else {
+            match scope.get_value::<Dynamic>("LIMITS") {
+                Some(limits) => limits,
+                None => return Ok(ScriptLimits::default()),
+            }
+        };
+        limits
+            .try_cast::<Map>()
+            .ok_or_else(|| "expected a map".to_owned())
+            .and_then(|limits| ScriptLimits::from_map(&limits))
+            .map_err(|message| ScriptError::Runtime {
+                script: self.name.clone(),
+                message: format!("invalid limits: {}", message),
+                position: rhai::Position::NONE,
+            })
+    }
+
+    pub fn limits(&self) -> ScriptLimits {
+        self.limits
+    }
+
+    /// whether the script went over one of its limits, see `ScriptLimits`
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub(crate) fn disable(&mut self) {
+        self.disabled = true;
+    }
+
+    /// call a function defined in the script, keeping the script's scope between calls
+    pub fn call(
+        &mut self,
+        engine: &Engine,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<Dynamic, ScriptError> {
+        let mut scope = std::mem::take(&mut self.scope);
+        let result = self.call_in(engine, &mut scope, name, args);
+        self.scope = scope;
+        if matches!(&result, Err(err) if err.is_limit_exceeded()) {
+            self.disable();
+        }
+        result
+    }
+
+    /// call a function defined in the script with a scope other than its own
+    pub fn call_in(
+        &self,
+        engine: &Engine,
+        scope: &mut Scope<'static>,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<Dynamic, ScriptError> {
+        let mut arg_values = Vec::new();
+        args.parse(&mut arg_values);
+        if !self.has_fn(name, arg_values.len()) {
+            return Err(ScriptError::MissingFunction {
+                script: self.name.clone(),
+                function: name.to_owned(),
+            });
+        }
+
+        // the top level statements already ran when the scope was made
+        let value = limits::enter(self.limits, || {
+            engine.call_fn_raw(scope, &self.script_ast, false, true, name, None, arg_values)
+        })
+        .map_err(|error| ScriptError::runtime(&self.name, *error))?;
+        self.check_sizes(scope)?;
+        self.limits
+            .check_value(&value, true)
+            .map_err(|limit| ScriptError::limit_exceeded(&self.name, limit))?;
+        Ok(value)
+    }
+
+    /// a fresh scope holding the variables set by the script's top level statements
+    pub fn new_scope(&self, engine: &Engine) -> Result<Scope<'static>, ScriptError> {
+        let mut scope = Scope::new();
+        limits::enter(self.limits, || {
+            engine.run_ast_with_scope(&mut scope, &self.script_ast)
+        })
+        .map_err(|error| ScriptError::runtime(&self.name, *error))?;
+        self.check_sizes(&scope)?;
+        Ok(scope)
+    }
+
+    /// check everything `scope` holds against the script's own size limits, which are
+    /// only checked on the values in scope while it runs
+    fn check_sizes(&self, scope: &Scope<'_>) -> Result<(), ScriptError> {
+        self.limits
+            .check_scope(scope, true)
+            .map_err(|limit| ScriptError::limit_exceeded(&self.name, limit))
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// swap in a recompiled AST, re-running its top level statements and `load`
+    /// in a fresh scope. The old version is kept if any of that fails, otherwise
+    /// its `unload` is called before it's replaced.
+    pub fn reload(&mut self, engine: &Engine, ast: AST, version: u64) -> Result<(), ScriptError> {
+        let mut reloaded = Script {
+            name: self.name.clone(),
+            script_ast: ast,
+            scope: Scope::new(),
+            last_run: self.last_run,
+            version,
+            fixed_step: self.fixed_step,
+            own_step: self.own_step,
+            accumulator: self.accumulator,
+            unloaded: false,
+            limits: ScriptLimits::default(),
+            disabled: false,
+        };
+        reloaded.limits = reloaded.declared_limits(engine)?;
+        reloaded.scope = reloaded.new_scope(engine)?;
+        reloaded.call(engine, "load", ())?;
+
+        // the new version is in place either way
+        if let Err(err) = self.unload(engine) {
+            println!("{}", err);
+        }
+        *self = reloaded;
+        Ok(())
+    }
+
+    /// call the script's `unload`, if it defines one and it wasn't unloaded already
+    pub fn unload(&mut self, engine: &Engine) -> Result<(), ScriptError> {
+        if self.unloaded {
+            return Ok(());
+        }
+        self.unloaded = true;
+
+        if self.has_fn("unload", 0) {
+            self.call(engine, "unload", ())?;
+        }
+        Ok(())
+    }
+
+    pub fn is_unloaded(&self) -> bool {
+        self.unloaded
+    }
+
+    pub fn fixed_step(&self) -> f64 {
+        self.fixed_step
+    }
+
+    /// run `fixed_update` every `step` seconds. The script keeps this step even when
+    /// the world's `Time` has another `fixed_step`. A step that isn't positive is
+    /// ignored.
+    pub fn set_fixed_step(&mut self, step: f64) {
+        if step > 0.0 {
+            self.fixed_step = step;
+            self.own_step = true;
+        }
+    }
+
+    /// use the step of the world's `Time`, unless the script has a step of its own
+    pub(crate) fn follow_fixed_step(&mut self, step: f64) {
+        if !self.own_step && step > 0.0 {
+            self.fixed_step = step;
+        }
+    }
+
+    /// move the script's clock forward by `delta` seconds
+    pub fn frame(&mut self, delta: f64) -> Frame {
+        self.accumulator += delta;
+        let steps = (self.accumulator / self.fixed_step).floor() as u32;
+        let steps = steps.min(MAX_FIXED_STEPS);
+        self.accumulator -= steps as f64 * self.fixed_step;
+        if self.accumulator >= self.fixed_step {
+            self.accumulator %= self.fixed_step;
+        }
+
+        Frame {
+            delta,
+            step: self.fixed_step,
+            steps,
+        }
+    }
+
+    /// run the update phases the script defines for `frame`, stopping at the first
+    /// that fails
+    pub fn run_frame(&mut self, engine: &Engine, frame: Frame) -> Result<(), ScriptError> {
+        for _ in 0..frame.steps {
+            self.call_phase(engine, "fixed_update", frame.step)?;
+        }
+        self.call_phase(engine, "update", frame.delta)?;
+        self.call_phase(engine, "late_update", frame.delta)
+    }
+
+    fn call_phase(&mut self, engine: &Engine, phase: &str, delta: f64) -> Result<(), ScriptError> {
+        if self.has_fn(phase, 1) {
+            self.call(engine, phase, (delta,))?;
+        }
+        Ok(())
+    }
+
+    /// run the update phases with the time since the script last ran
+    pub fn update(&mut self, engine: &Engine) -> Result<(), ScriptError> {
+        let delta = self.restart_clock();
+        self.update_by(engine, delta)
+    }
+
+    /// run the update phases for `delta` seconds, unless the script is disabled
+    pub fn update_by(&mut self, engine: &Engine, delta: f64) -> Result<(), ScriptError> {
+        if self.disabled {
+            return Ok(());
+        }
+        let frame = self.frame(delta);
+        self.run_frame(engine, frame)
+    }
+
+    /// whether the script is updated once per attached entity, with phases taking the
+    /// entity first, like `update(entity, delta)`
+    pub fn is_per_entity(&self) -> bool {
+        PHASES.iter().any(|phase| self.has_fn(phase, 2))
+    }
+
+    /// the time since the script last ran, restarting the count
+    pub(crate) fn restart_clock(&mut self) -> f64 {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_run).as_secs_f64();
+        self.last_run = now;
+        delta
+    }
+
+    /// run the update phases for `entity` with its own scope, like
+    /// `update(entity, delta)`, setting the scope up first if this version of the
+    /// script hasn't run for the entity yet
+    pub fn update_instance(
+        &self,
+        engine: &Engine,
+        instance: &mut ScriptInstance,
+        entity: Entity,
+        frame: Frame,
+    ) -> Result<(), ScriptError> {
+        if instance.scope_for(self.version).is_none() {
+            instance.set_scope(self.new_scope(engine)?, self.version);
+        }
+        let scope = instance
+            .scope_for(self.version)
+            .expect("bug: the instance scope was just set");
+
+        let mut call_phase = |phase: &str, delta: f64| {
+            if self.has_fn(phase, 2) {
+                self.call_in(engine, scope, phase, (entity, delta))?;
+            }
+            Ok(())
+        };
+        for _ in 0..frame.steps {
+            call_phase("fixed_update", frame.step)?;
+        }
+        call_phase("update", frame.delta)?;
+        call_phase("late_update", frame.delta)
+    }
+}
+
+pub struct HelloWorld;
+
+impl<'a> System<'a> for HelloWorld {
+    type SystemData = ReadStorage<'a, Position>;
+
+    fn run(&mut self, position: Self::SystemData) {
+        use specs::Join;
+
+        for position in position.join() {
+            println!("Hello, {:?}", &position);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::context::{self, Deferred, ScriptContext};
+use crate::system::{reload, take_own, write_own};
+use crate::{Dependencies, Script, ScriptError, ScriptStack, ScriptSystemData};
+use specs::prelude::*;
+use specs::shred::DynamicSystemData;
+use specs::storage::MaskedStorage;
+use specs::AccessorCow;
+use std::marker::PhantomData;
+
+/// the hooks a script can define for its component, called with the entity
+const HOOKS: [&str; 3] = ["on_added", "on_modified", "on_removed"];
+
+/// A system calling the lifecycle hooks the scripts of a component define:
+/// `on_added(entity)`, `on_modified(entity)` and `on_removed(entity)`.
+///
+/// The hooks are driven by the events of the component's `FlaggedStorage`, so they
+/// run the next time the system runs after the component changed. A hook changing
+/// the component itself triggers `on_modified` on the following run. A script going
+/// over its `ScriptLimits` is disabled, its hooks are skipped until it's reloaded.
+///
+/// The hooks are called on the scripts of the `ScriptStack<C>`, the same ones a
+/// `ScriptStackSystem<C>` updates, so a script's hooks and its update phases share
+/// its scope.
+pub struct LifecycleSystem<C> {
+    dependencies: Dependencies,
+    storage: ResourceId,
+    reader: Option<ReaderId<ComponentEvent>>,
+    errors: Vec<ScriptError>,
+    deferred: Deferred,
+    component: PhantomData<fn() -> C>,
+}
+
+impl<C> LifecycleSystem<C>
+where
+    C: Component,
+    C::Storage: Tracked,
+{
+    /// a system for the hooks of the scripts bound to `C`, which can use everything
+    /// they declare. `C` has to be registered with `WorldHelper::register_scriptable`.
+    pub fn new(world: &World) -> Self {
+        let mut dependencies = world
+            .write_resource::<ScriptStack<C>>()
+            .dependencies(&world.fetch(), &world.fetch());
+
+        // the events are read from the storage, which the scripts may already write
+        let storage = ResourceId::new::<MaskedStorage<C>>();
+        if !dependencies.writes.contains(&storage) && !dependencies.reads.contains(&storage) {
+            dependencies.reads.push(storage.clone());
+        }
+        write_own::<ScriptStack<C>>(&mut dependencies);
+
+        LifecycleSystem {
+            dependencies,
+            storage,
+            reader: None,
+            errors: Vec::new(),
+            deferred: Deferred::default(),
+            component: PhantomData,
+        }
+    }
+
+    /// whether `script` defines any lifecycle hook
+    pub fn has_hooks(script: &Script) -> bool {
+        HOOKS.iter().any(|hook| script.has_fn(hook, 1))
+    }
+
+    /// the errors from the hooks that failed during the last run, and from what they
+    /// deferred to the `World::maintain` before it
+    pub fn errors(&self) -> &[ScriptError] {
+        &self.errors
+    }
+
+    /// the hooks to call for the events since the last run, and their entities
+    fn events(&mut self, data: &ScriptSystemData<'_>) -> Vec<(&'static str, Entity)> {
+        let storage = data
+            .fetched(&self.dependencies, &self.storage)
+            .and_then(|storage| storage.downcast_ref::<MaskedStorage<C>>())
+            .expect("bug: lifecycle systems fetch the storage of their component");
+        let storage = Storage::new(data.entities.clone(), storage);
+        let reader = self
+            .reader
+            .as_mut()
+            .expect("`LifecycleSystem` used before it was set up");
+
+        storage
+            .channel()
+            .read(reader)
+            .map(|event| match *event {
+                ComponentEvent::Inserted(id) => ("on_added", id),
+                ComponentEvent::Modified(id) => ("on_modified", id),
+                ComponentEvent::Removed(id) => ("on_removed", id),
+            })
+            .map(|(hook, id)| (hook, data.entities.entity(id)))
+            // the component of a dead entity is removed when the world is maintained
+            .filter(|(hook, entity)| *hook == "on_removed" || data.entities.is_alive(*entity))
+            .collect()
+    }
+}
+
+impl<'a, C> System<'a> for LifecycleSystem<C>
+where
+    C: Component,
+    C::Storage: Tracked,
+{
+    type SystemData = ScriptSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let events = self.events(&data);
+        // what the scripts deferred to `World::maintain` failed after the last run
+        self.errors = self.deferred.take();
+
+        let mut writes = std::mem::take(&mut data.writes);
+        let mut stack = take_own::<ScriptStack<C>>(&mut writes);
+
+        for bound in stack.iter_mut().filter(|bound| bound.is_enabled()) {
+            let script = bound.script_mut();
+            if !Self::has_hooks(script) || data.library.is_removed(script.name()) {
+                continue;
+            }
+            let newer = data
+                .library
+                .newer(script.name(), script.version())
+                .map(|(ast, version)| (ast.clone(), version));
+            if newer.is_none() && events.is_empty() {
+                continue;
+            }
+
+            let name = script.name().to_owned();
+            let mut context = ScriptContext::new(&name, &self.dependencies, &data, &mut writes);
+            context.deferred = self.deferred.clone();
+            let engine = &data.engine;
+            context::enter(&mut context, || reload(script, engine, newer));
+            for &(hook, entity) in &events {
+                if script.is_disabled() {
+                    break;
+                }
+                if !script.has_fn(hook, 1) {
+                    continue;
+                }
+
+                let result = context::enter(&mut context, || script.call(engine, hook, (entity,)));
+                if let Err(err) = result {
+                    println!("{}", err);
+                    self.errors.push(err);
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        <ScriptSystemData as DynamicSystemData>::setup(&self.dependencies, world);
+        <WriteStorage<C> as SystemData>::setup(world);
+        self.reader = Some(world.write_storage::<C>().register_reader());
+    }
+
+    fn accessor<'b>(&'b self) -> AccessorCow<'a, 'b, Self> {
+        AccessorCow::Ref(&self.dependencies)
+    }
+}
@@ -0,0 +1,258 @@
+use rhai::debugger::DebuggerCommand;
+use rhai::{Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope};
+use std::cell::Cell;
+
+/// How much a script is allowed to do. `None` leaves a limit off.
+///
+/// Limits are set globally with `WorldHelper::set_limits`, for every script run by the
+/// world's engine, and a script can tighten any of them for itself in its manifest with
+/// `fn limits()` or `const LIMITS`, like
+/// `const LIMITS = #{max_operations: 10000, max_call_depth: 4};`. The manifest is read
+/// before the script's top level statements run, so they are held to it too.
+/// A script going over a limit fails with `ScriptError::LimitExceeded` and is disabled
+/// until it's reloaded.
+///
+/// The engine enforces the global limits itself. The limits scripts declare are
+/// enforced on any engine the world API is registered on, see `register_world_api`.
+/// A script with its own call depth or size limits is checked at every step, which
+/// makes it run slower.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScriptLimits {
+    /// the operations one call into the script can take
+    pub max_operations: Option<u64>,
+    /// how deep script functions can call each other
+    pub max_call_depth: Option<usize>,
+    /// the length of a string, in bytes
+    pub max_string_size: Option<usize>,
+    pub max_array_size: Option<usize>,
+    pub max_map_size: Option<usize>,
+    /// the variables alive in the script's scope at once, its top level ones included
+    pub max_variables: Option<usize>,
+}
+
+/// the names of the limits, as used by manifests and `ScriptError::LimitExceeded`
+const OPERATIONS: &str = "operations";
+const CALL_DEPTH: &str = "call depth";
+const STRING_SIZE: &str = "string size";
+const ARRAY_SIZE: &str = "array size";
+const MAP_SIZE: &str = "map size";
+const VARIABLES: &str = "variables";
+
+impl ScriptLimits {
+    const NONE: ScriptLimits = ScriptLimits {
+        max_operations: None,
+        max_call_depth: None,
+        max_string_size: None,
+        max_array_size: None,
+        max_map_size: None,
+        max_variables: None,
+    };
+
+    /// what `fn limits()` gets to work out a script's limits, it only returns a map
+    pub(crate) const MANIFEST: ScriptLimits = ScriptLimits {
+        max_operations: Some(10_000),
+        ..ScriptLimits::NONE
+    };
+
+    /// read limits from a manifest map like `#{max_operations: 10000}`
+    pub fn from_map(map: &Map) -> Result<Self, String> {
+        let mut limits = ScriptLimits::default();
+        for (key, value) in map {
+            let value = value
+                .as_int()
+                .ok()
+                .filter(|value| *value >= 0)
+                .ok_or_else(|| format!("`{}` has to be a positive integer", key))?;
+            let size = Some(value as usize);
+            match key.as_str() {
+                "max_operations" => limits.max_operations = Some(value as u64),
+                "max_call_depth" => limits.max_call_depth = size,
+                "max_string_size" => limits.max_string_size = size,
+                "max_array_size" => limits.max_array_size = size,
+                "max_map_size" => limits.max_map_size = size,
+                "max_variables" => limits.max_variables = size,
+                _ => return Err(format!("unknown limit `{}`", key)),
+            }
+        }
+        Ok(limits)
+    }
+
+    /// the tighter of each limit in `self` and `other`
+    pub fn tightened(self, other: ScriptLimits) -> Self {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        ScriptLimits {
+            max_operations: min(self.max_operations, other.max_operations),
+            max_call_depth: min(self.max_call_depth, other.max_call_depth),
+            max_string_size: min(self.max_string_size, other.max_string_size),
+            max_array_size: min(self.max_array_size, other.max_array_size),
+            max_map_size: min(self.max_map_size, other.max_map_size),
+            max_variables: min(self.max_variables, other.max_variables),
+        }
+    }
+
+    /// make these the global limits of `engine`, and have it enforce the limits scripts
+    /// declare for themselves. See `WorldHelper::set_limits`, which applies them again
+    /// after the world API is registered.
+    pub(crate) fn apply(self, engine: &mut Engine) {
+        // rhai counts 0 as no limit, but has a default call depth
+        let depth = self
+            .max_call_depth
+            .unwrap_or_else(|| Engine::new_raw().max_call_levels());
+        engine
+            .set_max_operations(self.max_operations.unwrap_or(0))
+            .set_max_call_levels(depth)
+            .set_max_string_size(self.max_string_size.unwrap_or(0))
+            .set_max_array_size(self.max_array_size.unwrap_or(0))
+            .set_max_map_size(self.max_map_size.unwrap_or(0));
+        install_hooks(engine, self.max_variables);
+    }
+
+    /// whether the script has to be checked at every step
+    fn checks_steps(&self) -> bool {
+        self.max_call_depth.is_some()
+            || self.max_string_size.is_some()
+            || self.max_array_size.is_some()
+            || self.max_map_size.is_some()
+    }
+
+    /// check the values in `scope` against the size limits, and what they hold if `deep`
+    pub(crate) fn check_scope(&self, scope: &Scope<'_>, deep: bool) -> Result<(), &'static str> {
+        if !self.checks_steps() {
+            return Ok(());
+        }
+        scope
+            .iter_raw()
+            .try_for_each(|(_, _, value)| self.check_value(value, deep))
+    }
+
+    /// check `value` against the size limits, and what it holds if `deep`
+    pub(crate) fn check_value(&self, value: &Dynamic, deep: bool) -> Result<(), &'static str> {
+        let within = |max: Option<usize>, size: usize| max.is_none_or(|max| size <= max);
+
+        if value.is::<ImmutableString>() {
+            let size = value.read_lock::<ImmutableString>().map_or(0, |s| s.len());
+            if !within(self.max_string_size, size) {
+                return Err(STRING_SIZE);
+            }
+        } else if value.is::<rhai::Array>() {
+            let array = value.read_lock::<rhai::Array>().expect("bug: checked type");
+            if !within(self.max_array_size, array.len()) {
+                return Err(ARRAY_SIZE);
+            }
+            if deep {
+                array
+                    .iter()
+                    .try_for_each(|item| self.check_value(item, deep))?;
+            }
+        } else if value.is::<Map>() {
+            let map = value.read_lock::<Map>().expect("bug: checked type");
+            if !within(self.max_map_size, map.len()) {
+                return Err(MAP_SIZE);
+            }
+            if deep {
+                map.values()
+                    .try_for_each(|item| self.check_value(item, deep))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// have `engine` hold scripts to the limits they declare, and to `max_variables`.
+/// The engine enforces its other global limits itself, the hooks add the script's own.
+#[allow(deprecated)]
+pub(crate) fn install_hooks(engine: &mut Engine, max_variables: Option<usize>) {
+    let global = ScriptLimits {
+        max_variables,
+        ..ScriptLimits::NONE
+    };
+    engine.on_progress(|operations| {
+        let max = current().max_operations?;
+        (operations > max).then(|| Dynamic::from(Exceeded(OPERATIONS)))
+    });
+    engine.on_def_var(move |is_runtime, _, context| {
+        // variables are also checked while parsing, with nothing running yet
+        if !is_runtime {
+            return Ok(true);
+        }
+        match current().tightened(global).max_variables {
+            Some(max) if context.scope().len() >= max => Err(exceeded(VARIABLES)),
+            _ => Ok(true),
+        }
+    });
+    // the debugger sees every step, scripts without step limits are let go on the first
+    engine.register_debugger(
+        || Dynamic::UNIT,
+        |context, _, _, _, _| {
+            let limits = current();
+            if !limits.checks_steps() {
+                return Ok(DebuggerCommand::Continue);
+            }
+            if matches!(limits.max_call_depth, Some(max) if context.call_level() > max) {
+                return Err(exceeded(CALL_DEPTH));
+            }
+            // what the values hold is checked once the call returns
+            limits
+                .check_scope(context.scope(), false)
+                .map_err(exceeded)?;
+            Ok(DebuggerCommand::StepInto)
+        },
+    );
+}
+
+/// the token a script is terminated with when it goes over one of its own limits
+#[derive(Clone, Debug)]
+struct Exceeded(&'static str);
+
+fn exceeded(limit: &'static str) -> Box<EvalAltResult> {
+    EvalAltResult::ErrorTerminated(Dynamic::from(Exceeded(limit)), rhai::Position::NONE).into()
+}
+
+/// the limit behind `error`, if it's one the script went over
+pub(crate) fn exceeded_limit(error: &EvalAltResult) -> Option<&'static str> {
+    match error {
+        EvalAltResult::ErrorTooManyOperations(_) => Some(OPERATIONS),
+        EvalAltResult::ErrorStackOverflow(_) => Some(CALL_DEPTH),
+        EvalAltResult::ErrorDataTooLarge(kind, _) => Some(if kind.contains("string") {
+            STRING_SIZE
+        } else if kind.contains("array") {
+            ARRAY_SIZE
+        } else {
+            MAP_SIZE
+        }),
+        EvalAltResult::ErrorTerminated(token, _) => {
+            token.read_lock::<Exceeded>().map(|exceeded| exceeded.0)
+        }
+        _ => None,
+    }
+}
+
+thread_local! {
+    /// the limits of the script running on this thread
+    static CURRENT: Cell<ScriptLimits> = const { Cell::new(ScriptLimits::NONE) };
+}
+
+fn current() -> ScriptLimits {
+    CURRENT.with(Cell::get)
+}
+
+/// puts the previous limits back into `CURRENT` when dropped
+struct Restore(ScriptLimits);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.0));
+    }
+}
+
+/// hold the script running in `f` to `limits`
+pub(crate) fn enter<R>(limits: ScriptLimits, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore(CURRENT.with(|current| current.replace(limits)));
+    f()
+}
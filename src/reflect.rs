@@ -0,0 +1,139 @@
+use crate::Method;
+use rhai::{Dynamic, EvalAltResult};
+use std::fmt;
+
+/// Methods scripts and generic code can call by name, usually implemented with
+/// `#[script_methods]` on an `impl` block.
+pub trait ScriptMethods {
+    /// the methods that can be called
+    fn methods() -> &'static [Method];
+
+    /// call a method taking `&self`
+    fn call_method(&self, name: &str, args: &[Dynamic]) -> Result<Dynamic, ReflectError>;
+
+    /// call any method
+    fn call_method_mut(&mut self, name: &str, args: &[Dynamic]) -> Result<Dynamic, ReflectError>;
+}
+
+/// Why reflecting on a scriptable component failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReflectError {
+    /// the component has no visible field with that name
+    UnknownField { component: String, field: String },
+    /// the field can only be read
+    ReadOnly { component: String, field: String },
+    /// a map converted to the component doesn't have the field
+    MissingField { component: String, field: String },
+    /// the value can't be converted to the field's type
+    InvalidValue {
+        component: String,
+        field: String,
+        message: String,
+    },
+    /// the component has no method with that name
+    UnknownMethod { component: String, method: String },
+    /// the method takes `&mut self` but was called through a shared reference
+    Immutable { component: String, method: String },
+    /// the method was called with the wrong number of arguments
+    WrongArgumentCount {
+        component: String,
+        method: String,
+        expected: usize,
+        found: usize,
+    },
+    /// an argument can't be converted to the parameter's type
+    InvalidArgument {
+        component: String,
+        method: String,
+        index: usize,
+        message: String,
+    },
+}
+
+impl ReflectError {
+    /// the error for a value the field `field` of `component` can't hold
+    pub fn invalid_value(component: &str, field: &str, error: impl fmt::Display) -> Self {
+        ReflectError::InvalidValue {
+            component: component.to_owned(),
+            field: field.to_owned(),
+            message: error.to_string(),
+        }
+    }
+
+    /// the error for an argument the method `method` of `component` can't take
+    pub fn invalid_argument(
+        component: &str,
+        method: &str,
+        index: usize,
+        error: impl fmt::Display,
+    ) -> Self {
+        ReflectError::InvalidArgument {
+            component: component.to_owned(),
+            method: method.to_owned(),
+            index,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectError::UnknownField { component, field } => {
+                write!(f, "{} has no field `{}`", component, field)
+            }
+            ReflectError::ReadOnly { component, field } => {
+                write!(f, "the field `{}` of {} is read only", field, component)
+            }
+            ReflectError::MissingField { component, field } => {
+                write!(f, "{} is missing the field `{}`", component, field)
+            }
+            ReflectError::InvalidValue {
+                component,
+                field,
+                message,
+            } => write!(
+                f,
+                "invalid value for the field `{}` of {}: {}",
+                field, component, message
+            ),
+            ReflectError::UnknownMethod { component, method } => {
+                write!(f, "{} has no method `{}`", component, method)
+            }
+            ReflectError::Immutable { component, method } => write!(
+                f,
+                "the method `{}` of {} changes it, but it can't be changed here",
+                method, component
+            ),
+            ReflectError::WrongArgumentCount {
+                component,
+                method,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the method `{}` of {} takes {} arguments, not {}",
+                method, component, expected, found
+            ),
+            ReflectError::InvalidArgument {
+                component,
+                method,
+                index,
+                message,
+            } => write!(
+                f,
+                "invalid argument {} for the method `{}` of {}: {}",
+                index, method, component, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+// lets rhai functions use `?` on reflection
+impl From<ReflectError> for Box<EvalAltResult> {
+    fn from(error: ReflectError) -> Self {
+        error.to_string().into()
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{DirectorySource, ScriptText};
+use rhai::{Engine, AST};
+use specs::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The latest compiled version of every script, shared with the script systems
+/// through the `World`.
+#[derive(Default)]
+pub struct ScriptLibrary {
+    scripts: HashMap<String, (AST, u64)>,
+    /// scripts whose systems should unload them and stop running them
+    removed: HashSet<String>,
+}
+
+impl ScriptLibrary {
+    /// publish a newly compiled version of the script `name`, returning its version
+    pub fn publish(&mut self, name: &str, ast: AST) -> u64 {
+        let version = self.scripts.get(name).map_or(1, |(_, version)| version + 1);
+        self.scripts.insert(name.to_owned(), (ast, version));
+        self.removed.remove(name);
+        version
+    }
+
+    /// mark the script `name` as removed, until a new version is published
+    pub fn remove(&mut self, name: &str) {
+        self.removed.insert(name.to_owned());
+    }
+
+    pub fn is_removed(&self, name: &str) -> bool {
+        self.removed.contains(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AST> {
+        self.scripts.get(name).map(|(ast, _)| ast)
+    }
+
+    /// the AST of `name` if it is newer than `version`
+    pub fn newer(&self, name: &str, version: u64) -> Option<(&AST, u64)> {
+        self.scripts
+            .get(name)
+            .filter(|(_, latest)| *latest > version)
+            .map(|(ast, latest)| (ast, *latest))
+    }
+}
+
+/// Watches a `DirectorySource` and recompiles `.rhai` files when they change.
+///
+/// Call `poll` once per tick; changed scripts are published to the `ScriptLibrary`
+/// and picked up by their `ScriptSystem` the next time it runs. Deleted scripts are
+/// removed from it, so their systems unload them.
+pub struct ScriptRegistry {
+    source: DirectorySource,
+    /// the name and modification time of every script file seen
+    modified: HashMap<PathBuf, (String, SystemTime)>,
+}
+
+impl ScriptRegistry {
+    /// start watching `source`. Scripts already in it are assumed to be loaded.
+    pub fn new(source: DirectorySource) -> Self {
+        let mut registry = ScriptRegistry {
+            source,
+            modified: HashMap::new(),
+        };
+        registry.modified = registry
+            .scan()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, path, modified)| (path, (name, modified)))
+            .collect();
+        registry
+    }
+
+    pub fn source(&self) -> &DirectorySource {
+        &self.source
+    }
+
+    /// recompile every script that changed since the last poll, returning the
+    /// names of the scripts that were published
+    pub fn poll(&mut self, world: &mut World) -> Vec<String> {
+        let mut reloaded = Vec::new();
+        let scanned = match self.scan() {
+            Some(scanned) => scanned,
+            None => return reloaded,
+        };
+
+        let deleted: Vec<PathBuf> = self
+            .modified
+            .keys()
+            .filter(|path| !scanned.iter().any(|(_, scanned, _)| scanned == *path))
+            .cloned()
+            .collect();
+        for path in deleted {
+            let (name, _) = self
+                .modified
+                .remove(&path)
+                .expect("bug: path was just listed");
+            world
+                .entry::<ScriptLibrary>()
+                .or_insert_with(ScriptLibrary::default)
+                .remove(&name);
+            println!("removed script {}", name);
+        }
+
+        for (name, path, modified) in scanned {
+            if self.modified.get(&path).map(|(_, time)| time) == Some(&modified) {
+                continue;
+            }
+            // remember the change even if it fails to compile so the error is
+            // only reported once per save
+            self.modified.insert(path.clone(), (name.clone(), modified));
+
+            let ast = ScriptText::from_file(&name, &path)
+                .and_then(|text| text.compile(&world.fetch::<Engine>()));
+            match ast {
+                Ok(ast) => {
+                    world
+                        .entry::<ScriptLibrary>()
+                        .or_insert_with(ScriptLibrary::default)
+                        .publish(&name, ast);
+                    reloaded.push(name);
+                }
+                Err(err) => println!("{}, keeping the old version", err),
+            }
+        }
+
+        reloaded
+    }
+
+    /// names, paths and modification times of all the scripts in the directory,
+    /// `None` if it can't be read
+    fn scan(&self) -> Option<Vec<(String, PathBuf, SystemTime)>> {
+        let files = match self.source.files() {
+            Ok(files) => files,
+            Err(err) => {
+                println!("error reading {}: {}", self.source.dir().display(), err);
+                return None;
+            }
+        };
+
+        let scanned = files
+            .into_iter()
+            .filter_map(|(name, path)| {
+                let modified = path.metadata().and_then(|meta| meta.modified()).ok()?;
+                Some((name, path, modified))
+            })
+            .collect();
+        Some(scanned)
+    }
+}
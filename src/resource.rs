@@ -0,0 +1,387 @@
+use crate::{ReflectError, ReflectionTable, ScriptableComponent};
+use rhai::{Dynamic, Map};
+use specs::prelude::*;
+use specs::shred::{Accessor, DynamicSystemData, Fetch, FetchMut};
+use specs::storage::MaskedStorage;
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+/// Maps resource names to resource ids.
+///
+/// Resources with their own rust type are registered with `register` and keep the id
+/// `World::insert` gives them. Resources sharing a rust type, like the ones defined at
+/// runtime, are registered with `register_dynamic`, which gives each one a unique id
+/// to insert it with.
+///
+/// The table also remembers the rust type of each resource, so a name can be looked up
+/// as a resource of a given type with `get_as`.
+pub struct ResourceTable {
+    map: HashMap<String, (ResourceId, TypeId)>,
+    /// the next dynamic id, 0 is left for `World::insert`
+    next_id: u64,
+    /// names scripts are defining, registered when the world is maintained
+    reserved: Mutex<HashSet<String>>,
+}
+
+impl Default for ResourceTable {
+    fn default() -> Self {
+        ResourceTable {
+            map: HashMap::new(),
+            next_id: 1,
+            reserved: Mutex::default(),
+        }
+    }
+}
+
+impl ResourceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// registers the resource of type `T`, replacing anything registered as `name`
+    pub fn register<T: Resource>(&mut self, name: &str) {
+        self.map
+            .insert(name.to_owned(), (ResourceId::new::<T>(), TypeId::of::<T>()));
+    }
+
+    /// registers the storage of a component, so scripts can declare access to it by name
+    pub fn register_component<C: Component>(&mut self, name: &str) {
+        self.register::<MaskedStorage<C>>(name);
+    }
+
+    /// registers a resource of type `T` under a new dynamic id, to be inserted with
+    /// `World::insert_by_id`
+    pub fn register_dynamic<T: Resource>(
+        &mut self,
+        name: &str,
+    ) -> Result<ResourceId, ResourceTableError> {
+        if self.map.contains_key(name) {
+            return Err(ResourceTableError::AlreadyRegistered(name.to_owned()));
+        }
+
+        let id = ResourceId::new_with_dynamic_id::<T>(self.next_id);
+        self.next_id += 1;
+        self.map
+            .insert(name.to_owned(), (id.clone(), TypeId::of::<T>()));
+        Ok(id)
+    }
+
+    /// forget `name`, returning its id. The resource itself stays in the world.
+    pub fn unregister(&mut self, name: &str) -> Option<ResourceId> {
+        self.map.remove(name).map(|(id, _)| id)
+    }
+
+    /// register the resource known as `from` as `to` instead
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), ResourceTableError> {
+        if self.map.contains_key(to) {
+            return Err(ResourceTableError::AlreadyRegistered(to.to_owned()));
+        }
+        let entry = self
+            .map
+            .remove(from)
+            .ok_or_else(|| ResourceTableError::Unknown(from.to_owned()))?;
+
+        self.map.insert(to.to_owned(), entry);
+        Ok(())
+    }
+
+    /// the id of `name`, if it is registered
+    pub fn get(&self, name: &str) -> Option<ResourceId> {
+        self.map.get(name).map(|(id, _)| id.clone())
+    }
+
+    /// the id of `name`, if it is registered as a resource of type `T`
+    pub fn get_as<T: Resource>(&self, name: &str) -> Option<ResourceId> {
+        match self.map.get(name) {
+            Some((id, type_id)) if *type_id == TypeId::of::<T>() => Some(id.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.map.contains_key(name)
+    }
+
+    /// keep `name` for a resource that is registered later, returning `false` if it is
+    /// registered or reserved already
+    pub(crate) fn reserve(&self, name: &str) -> bool {
+        !self.contains(name) && self.reserved().insert(name.to_owned())
+    }
+
+    /// give up the reservation of `name`
+    pub(crate) fn release(&self, name: &str) {
+        self.reserved().remove(name);
+    }
+
+    fn reserved(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.reserved.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(String::as_str)
+    }
+}
+
+/// Why a `ResourceTable` couldn't be changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResourceTableError {
+    /// the name is already taken
+    AlreadyRegistered(String),
+    /// nothing is registered with the name
+    Unknown(String),
+    /// the name belongs to another kind of resource than the one asked for
+    WrongKind(String),
+}
+
+impl fmt::Display for ResourceTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceTableError::AlreadyRegistered(name) => {
+                write!(f, "a resource is already registered as `{}`", name)
+            }
+            ResourceTableError::Unknown(name) => {
+                write!(f, "no resource is registered as `{}`", name)
+            }
+            ResourceTableError::WrongKind(name) => {
+                write!(f, "`{}` isn't a runtime resource", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResourceTableError {}
+
+/// A resource defined at runtime, with `define_resource` or by a script calling
+/// `define_resource("Weather", #{rain: 0.0, wind: 3})`.
+///
+/// Its fields are the ones it was defined with, and keep the type of the value they
+/// were defined with.
+#[derive(Clone, Debug)]
+pub struct ScriptableResource {
+    name: String,
+    fields: Map,
+}
+
+impl ScriptableResource {
+    pub fn new(name: &str, fields: Map) -> Self {
+        ScriptableResource {
+            name: name.to_owned(),
+            fields,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// every field by name
+    pub fn values(&self) -> &Map {
+        &self.fields
+    }
+
+    /// the field `name`, if it holds a `T`
+    pub fn value<T: Any + Clone>(&self, name: &str) -> Option<T> {
+        self.fields.get(name)?.clone().try_cast()
+    }
+}
+
+impl ScriptableComponent for ScriptableResource {
+    fn get_field(&self, name: &str) -> Result<Dynamic, ReflectError> {
+        self.fields
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ReflectError::UnknownField {
+                component: self.name.clone(),
+                field: name.to_owned(),
+            })
+    }
+
+    fn set_field(&mut self, name: &str, value: Dynamic) -> Result<(), ReflectError> {
+        set_typed(&self.name, &mut self.fields, name, value)
+    }
+
+    fn to_map(&self) -> Map {
+        self.fields.clone()
+    }
+
+    /// the resource gets no name, use `ScriptableResource::new` to give it one
+    fn from_map(map: Map) -> Result<Self, ReflectError> {
+        Ok(ScriptableResource::new("", map))
+    }
+
+    // scripts reach it through `resource` like any other resource
+    fn register_rhai(_engine: &mut rhai::Engine) {}
+}
+
+/// set an existing field of `fields`, as long as `value` has the type it holds
+pub(crate) fn set_typed(
+    component: &str,
+    fields: &mut Map,
+    name: &str,
+    value: Dynamic,
+) -> Result<(), ReflectError> {
+    let field = fields
+        .get_mut(name)
+        .ok_or_else(|| ReflectError::UnknownField {
+            component: component.to_owned(),
+            field: name.to_owned(),
+        })?;
+    if field.type_name() != value.type_name() {
+        let message = format!("expected {}, got {}", field.type_name(), value.type_name());
+        return Err(ReflectError::invalid_value(component, name, message));
+    }
+
+    *field = value;
+    Ok(())
+}
+
+/// Insert a `ScriptableResource` called `name` into the world, registering it in the
+/// world's `ResourceTable` so systems created afterwards can declare it.
+pub fn define_resource(
+    world: &mut World,
+    name: &str,
+    fields: Map,
+) -> Result<ResourceId, ResourceTableError> {
+    let id = world
+        .entry::<ResourceTable>()
+        .or_insert_with(ResourceTable::new)
+        .register_dynamic::<ScriptableResource>(name)?;
+    let resource = ScriptableResource::new(name, fields);
+    world
+        .entry::<ReflectionTable>()
+        .or_insert_with(ReflectionTable::new)
+        .register(&resource);
+    world.insert_by_id(id.clone(), resource);
+
+    Ok(id)
+}
+
+/// the `ScriptableResource` called `name`, `None` if there's no such resource or
+/// `name` is another kind of resource
+pub fn fetch_resource<'a>(world: &'a World, name: &str) -> Option<Fetch<'a, ScriptableResource>> {
+    let id = world
+        .try_fetch::<ResourceTable>()?
+        .get_as::<ScriptableResource>(name)?;
+    world.try_fetch_by_id(id)
+}
+
+/// the `ScriptableResource` called `name`, to change it
+pub fn fetch_resource_mut<'a>(
+    world: &'a World,
+    name: &str,
+) -> Option<FetchMut<'a, ScriptableResource>> {
+    let id = world
+        .try_fetch::<ResourceTable>()?
+        .get_as::<ScriptableResource>(name)?;
+    world.try_fetch_mut_by_id(id)
+}
+
+/// The runtime resources a native system declares it reads and writes, by name.
+///
+/// Systems using `ScriptingResData` return this from `System::accessor`, so the
+/// dispatcher schedules them around other systems using the same resources.
+#[derive(Clone, Debug)]
+pub struct ScriptingResAccessor {
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+}
+
+impl ScriptingResAccessor {
+    /// resolve the names through the world's `ResourceTable`. Every name has to be a
+    /// `ScriptableResource`, without a table none is.
+    pub fn new(reads: &[&str], writes: &[&str], world: &World) -> Result<Self, ResourceTableError> {
+        let table = world.try_fetch::<ResourceTable>();
+        let resolve = |names: &[&str]| {
+            names
+                .iter()
+                .map(|&name| match table.as_deref() {
+                    Some(table) => match table.get_as::<ScriptableResource>(name) {
+                        Some(id) => Ok(id),
+                        None if table.contains(name) => {
+                            Err(ResourceTableError::WrongKind(name.to_owned()))
+                        }
+                        None => Err(ResourceTableError::Unknown(name.to_owned())),
+                    },
+                    None => Err(ResourceTableError::Unknown(name.to_owned())),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(ScriptingResAccessor {
+            reads: resolve(reads)?,
+            writes: resolve(writes)?,
+        })
+    }
+}
+
+impl Accessor for ScriptingResAccessor {
+    fn try_new() -> Option<Self> {
+        // there's no default for this
+        None
+    }
+
+    fn reads(&self) -> Vec<ResourceId> {
+        self.reads.clone()
+    }
+
+    fn writes(&self) -> Vec<ResourceId> {
+        self.writes.clone()
+    }
+}
+
+/// The runtime resources declared by a `ScriptingResAccessor`, in the order they
+/// were declared.
+pub struct ScriptingResData<'a> {
+    pub reads: Vec<Fetch<'a, ScriptableResource>>,
+    pub writes: Vec<FetchMut<'a, ScriptableResource>>,
+}
+
+impl<'a> ScriptingResData<'a> {
+    /// a resource declared as read or written
+    pub fn read(&self, name: &str) -> Option<&ScriptableResource> {
+        self.reads
+            .iter()
+            .map(|resource| &**resource)
+            .chain(self.writes.iter().map(|resource| &**resource))
+            .find(|resource| resource.name() == name)
+    }
+
+    /// a resource declared as written
+    pub fn write(&mut self, name: &str) -> Option<&mut ScriptableResource> {
+        self.writes
+            .iter_mut()
+            .map(|resource| &mut **resource)
+            .find(|resource| resource.name() == name)
+    }
+}
+
+impl<'a> DynamicSystemData<'a> for ScriptingResData<'a> {
+    type Accessor = ScriptingResAccessor;
+
+    fn setup(_accessor: &Self::Accessor, _world: &mut World) {}
+
+    fn fetch(access: &ScriptingResAccessor, world: &'a World) -> Self {
+        ScriptingResData {
+            reads: access
+                .reads
+                .iter()
+                .map(|id| {
+                    world
+                        .try_fetch_by_id(id.clone())
+                        .expect("runtime resource no longer exists")
+                })
+                .collect(),
+            writes: access
+                .writes
+                .iter()
+                .map(|id| {
+                    world
+                        .try_fetch_mut_by_id(id.clone())
+                        .expect("runtime resource no longer exists")
+                })
+                .collect(),
+        }
+    }
+}
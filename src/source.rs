@@ -0,0 +1,250 @@
+use crate::{Script, ScriptError};
+use rhai::{Engine, AST};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// environment variable naming the scripts directory when none is passed on the command line
+pub const SCRIPTS_DIR_VAR: &str = "RHAI_SCRIPTS_DIR";
+
+/// The source code of a script, before it's compiled.
+#[derive(Clone, Debug)]
+pub struct ScriptText {
+    pub name: String,
+    /// the file the script was read from, if any
+    pub path: Option<PathBuf>,
+    pub source: String,
+}
+
+impl ScriptText {
+    /// read the script `name` from a file
+    pub fn from_file(name: &str, path: &Path) -> Result<Self, ScriptError> {
+        let source = std::fs::read_to_string(path).map_err(|error| ScriptError::Io {
+            script: name.to_owned(),
+            path: path.to_owned(),
+            error,
+        })?;
+
+        Ok(ScriptText {
+            name: name.to_owned(),
+            path: Some(path.to_owned()),
+            source,
+        })
+    }
+
+    /// compile the script without running it
+    pub fn compile(&self, engine: &Engine) -> Result<AST, ScriptError> {
+        engine
+            .compile(&self.source)
+            .map_err(|error| ScriptError::parse(&self.name, error))
+    }
+
+    /// compile the script, run its top level statements and call `load`
+    pub fn load(&self, engine: &Engine) -> Result<Script, ScriptError> {
+        Script::load(&self.name, self.compile(engine)?, engine)
+    }
+}
+
+/// Somewhere scripts can be loaded from.
+pub trait ScriptSource {
+    /// the text of every script this source provides
+    fn read_scripts(&self) -> Vec<Result<ScriptText, ScriptError>>;
+
+    /// load every script, returning the ones that loaded and the errors of the
+    /// ones that didn't
+    fn load_scripts(&self, engine: &Engine) -> (Vec<Script>, Vec<ScriptError>) {
+        let mut scripts = Vec::new();
+        let mut errors = Vec::new();
+
+        for text in self.read_scripts() {
+            match text.and_then(|text| text.load(engine)) {
+                Ok(script) => scripts.push(script),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        (scripts, errors)
+    }
+}
+
+/// Scripts read from `.rhai` files in a directory.
+///
+/// Scripts are named by their path relative to the directory without the
+/// extension, so `scripts/enemies/goblin.rhai` is `enemies/goblin`.
+#[derive(Clone, Debug)]
+pub struct DirectorySource {
+    dir: PathBuf,
+    recursive: bool,
+}
+
+impl DirectorySource {
+    /// read scripts from `dir` and all of its subdirectories
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DirectorySource {
+            dir: dir.into(),
+            recursive: true,
+        }
+    }
+
+    /// the directory given as the first argument, otherwise the one in
+    /// `RHAI_SCRIPTS_DIR`, otherwise `./scripts`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+        let dir = args
+            .into_iter()
+            .next()
+            .or_else(|| std::env::var(SCRIPTS_DIR_VAR).ok())
+            .unwrap_or_else(|| "scripts".to_owned());
+
+        DirectorySource::new(dir)
+    }
+
+    /// whether scripts in subdirectories are included
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// every script file in the directory, with its name
+    pub fn files(&self) -> std::io::Result<Vec<(String, PathBuf)>> {
+        let mut files = Vec::new();
+        self.collect_files(&self.dir, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    fn collect_files(&self, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+        for entry in dir.read_dir()? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if self.recursive {
+                    self.collect_files(&path, files)?;
+                }
+            } else if path.extension().is_some_and(|ext| ext == "rhai") {
+                files.push((script_name(&self.dir, &path), path));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// the name of the script at `path` in the scripts directory `dir`: its path relative to
+/// `dir` without the extension, so `scripts/enemies/goblin.rhai` is `enemies/goblin`.
+/// A script outside of `dir` is named by its file name.
+pub(crate) fn script_name(dir: &Path, path: &Path) -> String {
+    let relative = path
+        .strip_prefix(dir)
+        .map(Path::to_owned)
+        .ok()
+        .or_else(|| {
+            // `dir` may be relative and `path` absolute, or the other way around
+            let dir = dir.canonicalize().ok()?;
+            let path = path.canonicalize().ok()?;
+            path.strip_prefix(dir).map(Path::to_owned).ok()
+        })
+        .unwrap_or_else(|| {
+            path.file_name()
+                .map_or_else(|| path.to_owned(), PathBuf::from)
+        });
+    relative
+        .with_extension("")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl ScriptSource for DirectorySource {
+    fn read_scripts(&self) -> Vec<Result<ScriptText, ScriptError>> {
+        match self.files() {
+            Ok(files) => files
+                .iter()
+                .map(|(name, path)| ScriptText::from_file(name, path))
+                .collect(),
+            Err(error) => vec![Err(ScriptError::Io {
+                script: self.dir.display().to_string(),
+                path: self.dir.clone(),
+                error,
+            })],
+        }
+    }
+}
+
+/// Scripts kept in memory as name → source code, mostly useful for tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySource {
+    scripts: BTreeMap<String, String>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add the script `name`, replacing any script with the same name
+    pub fn with(mut self, name: &str, source: &str) -> Self {
+        self.insert(name, source);
+        self
+    }
+
+    pub fn insert(&mut self, name: &str, source: &str) {
+        self.scripts.insert(name.to_owned(), source.to_owned());
+    }
+}
+
+impl ScriptSource for MemorySource {
+    fn read_scripts(&self) -> Vec<Result<ScriptText, ScriptError>> {
+        self.scripts
+            .iter()
+            .map(|(name, source)| {
+                Ok(ScriptText {
+                    name: name.clone(),
+                    path: None,
+                    source: source.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Scripts embedded in the binary at compile time, see `embed_scripts!`.
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedSource {
+    scripts: &'static [(&'static str, &'static str)],
+}
+
+impl EmbeddedSource {
+    pub const fn new(scripts: &'static [(&'static str, &'static str)]) -> Self {
+        EmbeddedSource { scripts }
+    }
+}
+
+impl ScriptSource for EmbeddedSource {
+    fn read_scripts(&self) -> Vec<Result<ScriptText, ScriptError>> {
+        self.scripts
+            .iter()
+            .map(|(name, source)| {
+                Ok(ScriptText {
+                    name: (*name).to_owned(),
+                    path: None,
+                    source: (*source).to_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Embed scripts in the binary, with paths relative to the current file.
+///
+/// ```ignore
+/// let source = embed_scripts!["test" => "../scripts/test.rhai"];
+/// ```
+#[macro_export]
+macro_rules! embed_scripts {
+    ($($name:literal => $path:literal),* $(,)?) => {
+        $crate::EmbeddedSource::new(&[$(($name, include_str!($path))),*])
+    };
+}
@@ -0,0 +1,233 @@
+use crate::context::{self, Deferred, ScriptContext};
+use crate::system::{changes, delta, register_readers, reload, take_own, write_own, Readers};
+use crate::{ComponentScript, Dependencies, ResourceTable, Script, ScriptError, ScriptSystemData};
+use rhai::{Engine, INT};
+use specs::prelude::*;
+use specs::shred::DynamicSystemData;
+use specs::AccessorCow;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// The scripts bound to the component `C`, in the order they run, see
+/// `WorldHelper::register_scriptable`.
+///
+/// The stack is kept in the world, so the systems running its scripts see them
+/// enabled, disabled or moved while the game runs.
+pub struct ScriptStack<C> {
+    scripts: Vec<ComponentScript>,
+    component: PhantomData<fn() -> C>,
+}
+
+impl<C> Default for ScriptStack<C> {
+    fn default() -> Self {
+        ScriptStack::new(Vec::new())
+    }
+}
+
+impl<C> ScriptStack<C> {
+    /// `scripts` ordered by their priority
+    pub(crate) fn new(scripts: Vec<ComponentScript>) -> Self {
+        ScriptStack {
+            scripts,
+            component: PhantomData,
+        }
+    }
+
+    /// the enabled scripts, in the order they run
+    pub fn enabled(&self) -> impl Iterator<Item = &Script> {
+        self.scripts
+            .iter()
+            .filter(|bound| bound.is_enabled())
+            .map(ComponentScript::script)
+    }
+
+    /// enable or disable the script `script`, returning whether it is in the stack
+    pub fn set_enabled(&mut self, script: &str, enabled: bool) -> bool {
+        match self.get_mut(script) {
+            Some(bound) => {
+                bound.set_enabled(enabled);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// move the script `script` to `priority`, returning whether it is in the stack.
+    /// Scripts with equal priorities keep their order.
+    pub fn set_priority(&mut self, script: &str, priority: INT) -> bool {
+        match self.get_mut(script) {
+            Some(bound) => bound.priority = priority,
+            None => return false,
+        }
+        self.scripts.sort_by_key(ComponentScript::priority);
+        true
+    }
+
+    fn get_mut(&mut self, script: &str) -> Option<&mut ComponentScript> {
+        self.scripts
+            .iter_mut()
+            .find(|bound| bound.script().name() == script)
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut ComponentScript> {
+        self.scripts.iter_mut()
+    }
+
+    /// everything the scripts of the stack declare they read or write
+    pub(crate) fn dependencies(&mut self, engine: &Engine, table: &ResourceTable) -> Dependencies {
+        let mut dependencies = Dependencies::new(Vec::new(), Vec::new());
+        for bound in &mut self.scripts {
+            let declared = Dependencies::from_script(bound.script_mut(), engine, table);
+            for id in declared.writes {
+                if !dependencies.writes.contains(&id) {
+                    dependencies.writes.push(id);
+                }
+            }
+            for id in declared.reads {
+                if !dependencies.reads.contains(&id) {
+                    dependencies.reads.push(id);
+                }
+            }
+            for name in declared.unknown {
+                if !dependencies.unknown.contains(&name) {
+                    dependencies.unknown.push(name);
+                }
+            }
+        }
+        dependencies
+            .reads
+            .retain(|id| !dependencies.writes.contains(id));
+        dependencies
+    }
+}
+
+impl<C> Deref for ScriptStack<C> {
+    type Target = [ComponentScript];
+
+    fn deref(&self) -> &[ComponentScript] {
+        &self.scripts
+    }
+}
+
+/// A system running the update phases of the enabled scripts bound to `C`, one after
+/// the other in the order of their priority, see `Script`.
+///
+/// The scripts are taken from the `ScriptStack<C>` on every run, so enabling,
+/// disabling or moving one takes effect on the next run. The system can use
+/// everything the scripts declare. Scripts updating every entity on its own, like
+/// `update(entity, delta)`, can't be bound to a component, see `BindingError::PerEntity`.
+///
+/// `query_changed` works like in a `ScriptSystem`, each script of the stack when the
+/// system was set up counts its changes from then on.
+pub struct ScriptStackSystem<C> {
+    dependencies: Dependencies,
+    errors: Vec<ScriptError>,
+    deferred: Deferred,
+    /// the change readers of each script, by its name
+    readers: HashMap<String, Readers>,
+    component: PhantomData<fn() -> C>,
+}
+
+impl<C> ScriptStackSystem<C>
+where
+    C: Component,
+{
+    /// a system for the scripts bound to `C`, which has to be registered with
+    /// `WorldHelper::register_scriptable`
+    pub fn new(world: &World) -> Self {
+        let mut dependencies = world
+            .write_resource::<ScriptStack<C>>()
+            .dependencies(&world.fetch(), &world.fetch());
+        write_own::<ScriptStack<C>>(&mut dependencies);
+
+        ScriptStackSystem {
+            dependencies,
+            errors: Vec::new(),
+            deferred: Deferred::default(),
+            readers: HashMap::new(),
+            component: PhantomData,
+        }
+    }
+
+    /// the errors from the scripts that failed during the last run, and from what
+    /// they deferred to the `World::maintain` before it
+    pub fn errors(&self) -> &[ScriptError] {
+        &self.errors
+    }
+}
+
+impl<'a, C> System<'a> for ScriptStackSystem<C>
+where
+    C: Component,
+{
+    type SystemData = ScriptSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let mut stack = take_own::<ScriptStack<C>>(&mut data.writes);
+        // the time and the changes are read before the writes are split off, a script
+        // may write them
+        let time = data.time(&self.dependencies).cloned();
+        let mut changed: HashMap<_, _> = stack
+            .iter()
+            .filter(|bound| bound.is_enabled())
+            .filter_map(|bound| {
+                let name = bound.script().name();
+                let readers = self.readers.get_mut(name)?;
+                Some((name.to_owned(), changes(readers, &self.dependencies, &data)))
+            })
+            .collect();
+        let mut writes = std::mem::take(&mut data.writes);
+
+        // what the scripts deferred to `World::maintain` failed after the last run
+        self.errors = self.deferred.take();
+        for bound in stack.iter_mut().filter(|bound| bound.is_enabled()) {
+            let script = bound.script_mut();
+            let newer = data
+                .library
+                .newer(script.name(), script.version())
+                .map(|(ast, version)| (ast.clone(), version));
+            // a removed script is unloaded once, like in `ScriptSystem`
+            let removed = data.library.is_removed(script.name());
+            if removed && script.is_unloaded() {
+                continue;
+            }
+            let delta = delta(script, time.as_ref());
+
+            let name = script.name().to_owned();
+            let mut context = ScriptContext::new(&name, &self.dependencies, &data, &mut writes);
+            context.changed = changed.remove(&name).unwrap_or_default();
+            context.deferred = self.deferred.clone();
+            let engine = &data.engine;
+            let result = context::enter(&mut context, || {
+                reload(script, engine, newer);
+                if removed {
+                    script.unload(engine)
+                } else {
+                    script.update_by(engine, delta)
+                }
+            });
+            if let Err(err) = result {
+                println!("{}", err);
+                self.errors.push(err);
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        <ScriptSystemData as DynamicSystemData>::setup(&self.dependencies, world);
+        let names: Vec<_> = world
+            .fetch::<ScriptStack<C>>()
+            .iter()
+            .map(|bound| bound.script().name().to_owned())
+            .collect();
+        self.readers = names
+            .into_iter()
+            .map(|name| (name, register_readers(&self.dependencies, world)))
+            .collect();
+    }
+
+    fn accessor<'b>(&'b self) -> AccessorCow<'a, 'b, Self> {
+        AccessorCow::Ref(&self.dependencies)
+    }
+}
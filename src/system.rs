@@ -0,0 +1,292 @@
+use crate::context::{self, Deferred, ScriptContext};
+use crate::{
+    ComponentTable, Dependencies, ResourceTable, Script, ScriptError, ScriptInstance,
+    ScriptSystemData, Time,
+};
+use rhai::{Engine, AST};
+use specs::prelude::*;
+use specs::shred::cell::RefMut;
+use specs::shred::DynamicSystemData;
+use specs::storage::MaskedStorage;
+use specs::world::EntitiesRes;
+use specs::AccessorCow;
+use std::collections::HashMap;
+
+/// A system that runs a script's update phases inside a `Dispatcher`, see `Script`.
+///
+/// The script's reads and writes are declared through its `Dependencies`, so it
+/// can be scheduled alongside native systems.
+///
+/// A script defining `update(entity, delta)` instead of `update(delta)` (or another
+/// phase taking the entity) is run once for every entity with a `ScriptInstance` of
+/// it, each with the entity's own scope.
+///
+/// `query_changed` finds the entities whose tracked components were inserted or
+/// modified since the system last ran, counting from when it was set up.
+///
+/// A script going over its `ScriptLimits` is disabled, the system skips it until a
+/// newer version is published. Its error stays the last one.
+///
+/// What the script deferred to `World::maintain`, like `define_resource`, fails after
+/// the run. That error is the last one of the next run, unless the run fails itself.
+pub struct ScriptSystem {
+    script: Script,
+    dependencies: Dependencies,
+    per_entity: bool,
+    last_error: Option<ScriptError>,
+    readers: Readers,
+    deferred: Deferred,
+}
+
+/// the tracked components a script declared, and their change readers
+pub(crate) type Readers = Vec<(String, ReaderId<ComponentEvent>)>;
+
+impl ScriptSystem {
+    pub fn new(script: Script, mut dependencies: Dependencies) -> Self {
+        let per_entity = script.is_per_entity();
+        if per_entity {
+            write_own::<MaskedStorage<ScriptInstance>>(&mut dependencies);
+        }
+
+        ScriptSystem {
+            script,
+            dependencies,
+            per_entity,
+            last_error: None,
+            readers: Vec::new(),
+            deferred: Deferred::default(),
+        }
+    }
+
+    /// create a system whose dependencies are the ones declared by the script
+    pub fn from_script(mut script: Script, world: &World) -> Self {
+        let dependencies = Dependencies::from_script(
+            &mut script,
+            &world.fetch::<Engine>(),
+            &world.fetch::<ResourceTable>(),
+        );
+
+        ScriptSystem::new(script, dependencies)
+    }
+
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    /// what the system reads and writes, and the names the script declared that
+    /// aren't resources
+    pub fn dependencies(&self) -> &Dependencies {
+        &self.dependencies
+    }
+
+    /// the error from the last run, if it failed
+    pub fn last_error(&self) -> Option<&ScriptError> {
+        self.last_error.as_ref()
+    }
+}
+
+impl ScriptSystem {
+    /// update every entity the script is attached to. An entity whose update fails
+    /// doesn't stop the others, the last error is kept.
+    fn run_instances(
+        &mut self,
+        mut data: ScriptSystemData<'_>,
+        changed: HashMap<String, BitSet>,
+        newer: Option<(AST, u64)>,
+        delta: f64,
+        deferred: Option<ScriptError>,
+    ) {
+        let mut writes = std::mem::take(&mut data.writes);
+        let mut instances = take_own::<MaskedStorage<ScriptInstance>>(&mut writes);
+        let mut instances = Storage::new(data.entities.clone(), &mut *instances);
+
+        let name = self.script.name().to_owned();
+        let mut context = ScriptContext::new(&name, &self.dependencies, &data, &mut writes);
+        context.changed = changed;
+        context.deferred = self.deferred.clone();
+        context::enter(&mut context, || {
+            reload(&mut self.script, &data.engine, newer)
+        });
+        if self.script.is_disabled() {
+            return;
+        }
+
+        let frame = self.script.frame(delta);
+        let script = &self.script;
+        let engine = &data.engine;
+        self.last_error = deferred;
+        for (entity, instance) in (&*data.entities, &mut instances).join() {
+            if instance.script() != name {
+                continue;
+            }
+
+            let result = context::enter(&mut context, || {
+                script.update_instance(engine, instance, entity, frame)
+            });
+            if let Err(err) = result {
+                println!("{}", err);
+                // going over a limit stops the script for every entity
+                let exceeded = err.is_limit_exceeded();
+                self.last_error = Some(err);
+                if exceeded {
+                    break;
+                }
+            }
+        }
+        if self
+            .last_error
+            .as_ref()
+            .is_some_and(ScriptError::is_limit_exceeded)
+        {
+            self.script.disable();
+        }
+    }
+}
+
+/// the time since `script` last ran, from the `Time` resource if there is one
+pub(crate) fn delta(script: &mut Script, time: Option<&Time>) -> f64 {
+    match time {
+        Some(time) => {
+            script.follow_fixed_step(time.fixed_step);
+            time.delta
+        }
+        None => script.restart_clock(),
+    }
+}
+
+/// a change reader for each tracked component `dependencies` read or write, counting
+/// from now
+pub(crate) fn register_readers(dependencies: &Dependencies, world: &World) -> Readers {
+    let components = world.fetch::<ComponentTable>();
+    let entities = world.fetch::<EntitiesRes>();
+    let declared =
+        |id: &ResourceId| dependencies.reads.contains(id) || dependencies.writes.contains(id);
+    let mut readers = Vec::new();
+    for name in components.names() {
+        let access = components
+            .get(name)
+            .expect("bug: listed component is missing");
+        let id = access.storage_id();
+        if !declared(&id) {
+            continue;
+        }
+        let reader = world
+            .try_fetch_internal(id)
+            .and_then(|storage| access.register_reader(&mut **storage.borrow_mut(), &entities));
+        if let Some(reader) = reader {
+            readers.push((name.to_owned(), reader));
+        }
+    }
+    readers
+}
+
+/// the entities whose tracked components changed since `readers` last read, for the
+/// `changed` of the script's context
+pub(crate) fn changes(
+    readers: &mut Readers,
+    dependencies: &Dependencies,
+    data: &ScriptSystemData<'_>,
+) -> HashMap<String, BitSet> {
+    readers
+        .iter_mut()
+        .filter_map(|(name, reader)| {
+            let access = data.components.get(name)?;
+            let storage = data.fetched(dependencies, &access.storage_id())?;
+            Some((
+                name.clone(),
+                access.changed(storage, &data.entities, reader),
+            ))
+        })
+        .collect()
+}
+
+/// make a system write `T` besides what its scripts declare. It's listed last, so
+/// `take_own` can split it off from the scripts' own writes.
+pub(crate) fn write_own<T: Resource>(dependencies: &mut Dependencies) {
+    dependencies.writes.push(ResourceId::new::<T>());
+}
+
+/// split the resource added with `write_own` off from the fetched `writes`. The
+/// dependencies still list it, zipping them with the remaining writes leaves it out.
+pub(crate) fn take_own<'a, T: Resource>(
+    writes: &mut Vec<RefMut<'a, Box<dyn Resource>>>,
+) -> RefMut<'a, T> {
+    writes
+        .pop()
+        .expect("bug: the system's own write is missing")
+        .map(|resource| {
+            resource
+                .downcast_mut::<T>()
+                .expect("bug: the system's own write has the wrong type")
+        })
+}
+
+/// swap in the newer version of the script, if there is one
+pub(crate) fn reload(script: &mut Script, engine: &Engine, newer: Option<(AST, u64)>) {
+    if let Some((ast, version)) = newer {
+        match script.reload(engine, ast, version) {
+            Ok(()) => println!("reloaded script {}", script.name()),
+            Err(err) => println!("{}, keeping the old version", err),
+        }
+    }
+}
+
+impl<'a> System<'a> for ScriptSystem {
+    type SystemData = ScriptSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        // reloaded inside the script's context, so `load` and `unload` can use the world
+        let newer = data
+            .library
+            .newer(self.script.name(), self.script.version())
+            .map(|(ast, version)| (ast.clone(), version));
+
+        let changed = changes(&mut self.readers, &self.dependencies, &data);
+        let deferred = self.deferred.take().pop();
+        let delta = delta(&mut self.script, data.time(&self.dependencies));
+        // a removed script is unloaded once, and doesn't run until it's published again
+        let removed = data.library.is_removed(self.script.name());
+        if removed && self.script.is_unloaded() {
+            return;
+        }
+        // a disabled script waits for a newer version
+        if self.script.is_disabled() && newer.is_none() && !removed {
+            return;
+        }
+        if self.per_entity && !removed {
+            self.run_instances(data, changed, newer, delta, deferred);
+            return;
+        }
+
+        // the script reaches the fetched reads and writes through the world api
+        let name = self.script.name().to_owned();
+        let mut writes = std::mem::take(&mut data.writes);
+        let mut context = ScriptContext::new(&name, &self.dependencies, &data, &mut writes);
+        context.changed = changed;
+        context.deferred = self.deferred.clone();
+        let script = &mut self.script;
+        let engine = &data.engine;
+        self.last_error = context::enter(&mut context, || {
+            reload(script, engine, newer);
+            if removed {
+                script.unload(engine)
+            } else {
+                script.update_by(engine, delta)
+            }
+        })
+        .err()
+        .or(deferred);
+        if let Some(err) = &self.last_error {
+            println!("{}", err);
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        <ScriptSystemData as DynamicSystemData>::setup(&self.dependencies, world);
+        self.readers = register_readers(&self.dependencies, world);
+    }
+
+    fn accessor<'b>(&'b self) -> AccessorCow<'a, 'b, Self> {
+        AccessorCow::Ref(&self.dependencies)
+    }
+}
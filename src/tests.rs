@@ -0,0 +1,2101 @@
+use crate::{
+    fetch_dynamic_storage, fetch_dynamic_storage_mut, fetch_resource, fetch_resource_mut,
+    load_script, script_methods, tick, BindingError, Dependencies, DirectorySource, HelloWorld,
+    MemorySource, Position, ReflectError, ReflectionTable, ResourceTable, ResourceTableError,
+    Script, ScriptBinding, ScriptError, ScriptInstance, ScriptLibrary, ScriptLimits,
+    ScriptRegistry, ScriptSource, ScriptSystem, ScriptValue, Scriptable, ScriptableComponent,
+    ScriptingResAccessor, ScriptingResData, Time, TimeSystem, WorldHelper,
+};
+use rhai::{Dynamic, Engine};
+use specs::prelude::*;
+use specs::storage::MaskedStorage;
+use specs::{AccessorCow, Component};
+use std::path::PathBuf;
+
+fn scripts_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("scripts")
+}
+
+fn test_script_path() -> PathBuf {
+    scripts_dir().join("test.rhai")
+}
+
+/// define the runtime resource `Log`, with an integer field set to 0 for each of `fields`
+fn define_log(helper: &mut WorldHelper, fields: &[&str]) {
+    let log = fields
+        .iter()
+        .map(|&field| (field.into(), Dynamic::from(0 as rhai::INT)))
+        .collect();
+    helper.define_resource("Log", log).unwrap();
+}
+
+/// a system for each of `scripts`, set up in `world`
+fn script_systems(scripts: Vec<Script>, world: &mut World) -> Vec<ScriptSystem> {
+    let mut systems: Vec<ScriptSystem> = scripts
+        .into_iter()
+        .map(|script| ScriptSystem::from_script(script, world))
+        .collect();
+    for system in &mut systems {
+        System::setup(system, world);
+    }
+    systems
+}
+
+/// fail with the last error of `system`, if it has one
+fn assert_ran(system: &ScriptSystem) {
+    assert!(system.last_error().is_none(), "{:?}", system.last_error());
+}
+
+#[test]
+fn test_script_system_runs_in_dispatcher() {
+    let mut world: World = WorldExt::new();
+    world.register::<Position>();
+    world
+        .create_entity()
+        .with(Position { x: 4.0, y: 7.0 })
+        .build();
+
+    let engine = Engine::new();
+    let script = load_script(&scripts_dir(), &test_script_path(), &engine).unwrap();
+    world.insert(engine);
+
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(HelloWorld, "hello_world", &[])
+        .with(
+            ScriptSystem::new(script, Dependencies::new(vec![], vec![])),
+            "test",
+            &["hello_world"],
+        )
+        .build();
+    dispatcher.setup(&mut world);
+
+    for _ in 0..3 {
+        dispatcher.dispatch(&world);
+        world.maintain();
+    }
+
+    let mut system = ScriptSystem::new(
+        load_script(
+            &scripts_dir(),
+            &test_script_path(),
+            &world.fetch::<Engine>(),
+        )
+        .unwrap(),
+        Dependencies::new(vec![], vec![]),
+    );
+    system.run_now(&world);
+    system.run_now(&world);
+    let all: f64 = system.script().scope.get_value("all").unwrap();
+    assert!(all > 0.0);
+}
+
+#[test]
+fn test_script_declares_dependencies() {
+    let mut world: World = WorldExt::new();
+    world.register::<Position>();
+    world.insert(Engine::new());
+
+    let mut table = ResourceTable::new();
+    table.register_component::<Position>("Position");
+    world.insert(table);
+
+    let script = load_script(
+        &scripts_dir(),
+        &test_script_path(),
+        &world.fetch::<Engine>(),
+    )
+    .unwrap();
+    let system = ScriptSystem::from_script(script, &world);
+
+    let accessor = system.accessor();
+    assert!(accessor
+        .reads()
+        .contains(&ResourceId::new::<MaskedStorage<Position>>()));
+    assert!(accessor.writes().is_empty());
+
+    // a reader of `Position` can run in parallel with the script
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(HelloWorld, "hello_world", &[])
+        .with(system, "test", &[])
+        .build();
+    dispatcher.setup(&mut world);
+    dispatcher.dispatch(&world);
+}
+
+#[test]
+fn test_script_dependencies_are_listed_once() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Position>().unwrap();
+    define_log(&mut helper, &["entries"]);
+    let world = helper.world_mut();
+    world
+        .create_entity()
+        .with(Position { x: 1.0, y: 0.0 })
+        .build();
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "a_mover",
+            r#"
+            fn reads() { ["Position"] }
+            fn writes() { ["Position"] }
+            fn load() {}
+            fn update(delta) {
+                for entity in query("Position") { entity["Position"].x += 1.0; }
+            }
+            "#,
+        )
+        .with(
+            "b_logger",
+            r#"
+            fn reads() { ["Weather"] }
+            fn writes() { ["Log", "Log"] }
+            fn load() {}
+            fn update(delta) {
+                let log = resource_mut("Log");
+                log.entries += 1;
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty(), "{:?}", errors);
+    let systems = script_systems(scripts, world);
+
+    // a resource the script writes isn't read too, and unknown names are kept
+    let table = world.fetch::<ResourceTable>();
+    let position = table.get("Position").unwrap();
+    assert_eq!(systems[0].dependencies().writes(), vec![position.clone()]);
+    assert!(!systems[0].dependencies().reads().contains(&position));
+    assert_eq!(
+        systems[1].dependencies().writes(),
+        vec![table.get("Log").unwrap()]
+    );
+    assert_eq!(systems[1].dependencies().unknown(), ["Weather"]);
+    drop(table);
+
+    let mut dispatcher = systems
+        .into_iter()
+        .enumerate()
+        .fold(DispatcherBuilder::new(), |builder, (i, system)| {
+            builder.with(system, &format!("script_{}", i), &[])
+        })
+        .build();
+    dispatcher.dispatch(world);
+
+    assert_eq!(
+        world.read_storage::<Position>().join().next().unwrap().x,
+        2.0
+    );
+    let log = fetch_resource(world, "Log").unwrap();
+    assert_eq!(log.value::<rhai::INT>("entries"), Some(1));
+}
+
+#[test]
+fn test_changed_scripts_are_reloaded() {
+    let dir = std::env::temp_dir().join(format!("rhai_specs_reload_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("reload.rhai");
+    std::fs::write(&path, "let value = 1; fn load() {} fn update(delta) {}").unwrap();
+
+    let mut world: World = WorldExt::new();
+    world.insert(Engine::new());
+    world.insert(ResourceTable::new());
+
+    let script = load_script(&dir, &path, &world.fetch::<Engine>()).unwrap();
+    let mut system = ScriptSystem::from_script(script, &world);
+    System::setup(&mut system, &mut world);
+    let mut registry = ScriptRegistry::new(DirectorySource::new(&dir));
+
+    // a script that fails to compile leaves the old version running
+    std::fs::write(&path, "let value = ;").unwrap();
+    bump_modified(&path, 1);
+    assert!(registry.poll(&mut world).is_empty());
+    system.run_now(&world);
+    let value: i64 = system.script().scope.get_value("value").unwrap();
+    assert_eq!(value, 1);
+
+    std::fs::write(&path, "let value = 2; fn load() {} fn update(delta) {}").unwrap();
+    bump_modified(&path, 2);
+    assert_eq!(registry.poll(&mut world), vec!["reload".to_owned()]);
+    system.run_now(&world);
+    let value: i64 = system.script().scope.get_value("value").unwrap();
+    assert_eq!(value, 2);
+
+    // a deleted script is unloaded
+    std::fs::remove_file(&path).unwrap();
+    assert!(registry.poll(&mut world).is_empty());
+    assert!(world.fetch::<ScriptLibrary>().is_removed("reload"));
+    system.run_now(&world);
+    assert!(system.script().is_unloaded());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// move a file's modification time forward, filesystems don't always notice quick writes
+fn bump_modified(path: &std::path::Path, secs: u64) {
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(secs))
+        .unwrap();
+}
+
+#[test]
+fn test_failing_script_is_isolated() {
+    let dir = std::env::temp_dir().join(format!("rhai_specs_errors_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("broken.rhai"),
+        "fn load() {}\nfn update(delta) {\n    throw \"boom\"\n}",
+    )
+    .unwrap();
+    std::fs::write(dir.join("missing.rhai"), "fn update(delta) {}").unwrap();
+    std::fs::write(dir.join("syntax.rhai"), "fn load() {").unwrap();
+
+    let engine = Engine::new();
+    let mut scripts = vec![
+        load_script(&scripts_dir(), &test_script_path(), &engine).unwrap(),
+        load_script(&dir, &dir.join("broken.rhai"), &engine).unwrap(),
+    ];
+
+    match load_script(&dir, &dir.join("missing.rhai"), &engine) {
+        Err(ScriptError::MissingFunction { script, function }) => {
+            assert_eq!(script, "missing");
+            assert_eq!(function, "load");
+        }
+        other => panic!("expected a missing function, got {:?}", other.err()),
+    }
+    assert!(matches!(
+        load_script(&dir, &dir.join("syntax.rhai"), &engine),
+        Err(ScriptError::Parse { .. })
+    ));
+    assert!(matches!(
+        load_script(&dir, &dir.join("nothing.rhai"), &engine),
+        Err(ScriptError::Io { .. })
+    ));
+
+    let errors = tick(&mut scripts, &engine);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].script(), "broken");
+    assert_eq!(errors[0].position().and_then(|pos| pos.line()), Some(3));
+
+    // the working script still ran
+    let all: f64 = scripts[0].scope.get_value("all").unwrap();
+    assert!(all > 0.0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_script_sources() {
+    let engine = Engine::new();
+
+    let memory = MemorySource::new()
+        .with("good", "let value = 3; fn load() {}")
+        .with("bad", "fn load() {");
+    let (scripts, errors) = memory.load_scripts(&engine);
+    assert_eq!(scripts.len(), 1);
+    assert_eq!(scripts[0].name(), "good");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].script(), "bad");
+
+    let embedded = crate::embed_scripts!["test" => "../scripts/test.rhai"];
+    let (scripts, errors) = embedded.load_scripts(&engine);
+    assert!(errors.is_empty());
+    assert_eq!(scripts[0].name(), "test");
+
+    let dir = std::env::temp_dir().join(format!("rhai_specs_sources_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("enemies")).unwrap();
+    std::fs::write(dir.join("top.rhai"), "fn load() {}").unwrap();
+    std::fs::write(dir.join("enemies/goblin.rhai"), "fn load() {}").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+    let directory = DirectorySource::from_args(vec![dir.display().to_string()]);
+    let (scripts, errors) = directory.load_scripts(&engine);
+    assert!(errors.is_empty());
+    let names: Vec<&str> = scripts.iter().map(|script| script.name()).collect();
+    assert_eq!(names, vec!["enemies/goblin", "top"]);
+
+    let (scripts, _) = directory.recursive(false).load_scripts(&engine);
+    assert_eq!(scripts.len(), 1);
+
+    // single files are named the same way as the directory names them
+    let name = |path: &str| crate::source::script_name(&dir, &dir.join(path));
+    assert_eq!(name("enemies/goblin.rhai"), "enemies/goblin");
+    assert_eq!(name("a.b.rhai"), "a.b");
+    assert_eq!(
+        load_script(&scripts_dir(), &test_script_path(), &engine)
+            .unwrap()
+            .name(),
+        "test"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_scriptable_component_bindings() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Position>().unwrap();
+
+    let engine = helper.world().fetch::<Engine>();
+    let pos: Position = engine
+        .eval("let pos = Position(1.0, 2.0); pos.y = 3.0; pos.x += pos.y; pos")
+        .unwrap();
+    assert_eq!(pos.x, 4.0);
+    assert_eq!(pos.y, 3.0);
+
+    let table = helper.world().fetch::<ResourceTable>();
+    assert_eq!(
+        table.get("Position"),
+        Some(ResourceId::new::<MaskedStorage<Position>>())
+    );
+}
+
+#[derive(Component, Scriptable, Clone, Debug)]
+#[scriptable(rename = "Hp")]
+struct Health {
+    #[scriptable(rename = "hp")]
+    current: i32,
+    #[scriptable(readonly)]
+    max: i32,
+    #[scriptable(skip)]
+    regen_timer: f32,
+}
+
+#[test]
+fn test_derive_scriptable() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Health>().unwrap();
+    let engine = helper.world().fetch::<Engine>();
+
+    // the renamed component is registered under its new name
+    assert_eq!(<Health as ScriptableComponent>::name(), "Hp");
+    assert!(helper.world().fetch::<ResourceTable>().contains("Hp"));
+    let health: Health = engine.eval("let h = Hp(5, 10); h.hp += 1; h").unwrap();
+    assert_eq!(health.current, 6);
+    assert_eq!(health.max, 10);
+    assert_eq!(health.regen_timer, 0.0);
+
+    assert_eq!(engine.eval::<i64>("Hp(5, 10).max").unwrap(), 10);
+    assert!(engine.eval::<()>("let h = Hp(5, 10); h.max = 3;").is_err());
+    assert!(engine.eval::<Dynamic>("Hp(5, 10).regen_timer").is_err());
+    assert!(engine.eval::<Health>("Hp(\"five\", 10)").is_err());
+
+    let names: Vec<&str> = health.fields().iter().map(|field| field.name).collect();
+    assert_eq!(names, vec!["hp", "max"]);
+    assert_eq!(health.fields()[0].type_name, "i32");
+    assert!(health.fields()[1].read_only);
+
+    // integers too large for scripts saturate instead of wrapping around
+    assert_eq!(u64::MAX.to_dynamic().as_int(), Ok(rhai::INT::MAX));
+    assert_eq!(usize::MAX.to_dynamic().as_int(), Ok(rhai::INT::MAX));
+    assert_eq!((-5i8).to_dynamic().as_int(), Ok(-5));
+}
+
+#[test]
+fn test_scripts_query_entities() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Position>().unwrap();
+    helper.register_scriptable::<Health>().unwrap();
+    let world = helper.world_mut();
+
+    let moving = world
+        .create_entity()
+        .with(Position { x: 1.0, y: 0.0 })
+        .with(Health {
+            current: 5,
+            max: 10,
+            regen_timer: 0.0,
+        })
+        .build();
+    let still = world
+        .create_entity()
+        .with(Position { x: 1.0, y: 0.0 })
+        .build();
+
+    let source = MemorySource::new()
+        .with(
+            "mover",
+            r#"
+            fn reads() { ["Hp"] }
+            fn writes() { ["Position"] }
+            fn load() {}
+            fn update(delta) {
+                for entity in query(["Position", "Hp"]) {
+                    entity["Position"].x += entity["Hp"].hp;
+                }
+            }
+            "#,
+        )
+        .with(
+            "sneaky",
+            r#"
+            fn reads() { ["Position"] }
+            fn load() {}
+            fn update(delta) {
+                for entity in query("Position") {
+                    entity["Position"] = Position(0.0, 0.0);
+                }
+            }
+            "#,
+        );
+    let (scripts, errors) = source.load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+
+    let mut systems: Vec<ScriptSystem> = scripts
+        .into_iter()
+        .map(|script| ScriptSystem::from_script(script, world))
+        .collect();
+    for system in &mut systems {
+        System::setup(system, world);
+        system.run_now(world);
+    }
+
+    let positions = world.read_storage::<Position>();
+    assert_eq!(positions.get(moving).unwrap().x, 6.0);
+    assert_eq!(positions.get(still).unwrap().x, 1.0);
+
+    // a script can't write a component it only declared as a read
+    let error = systems[1].last_error().unwrap().to_string();
+    assert!(error.contains("did not declare that it writes `Position`"));
+}
+
+#[test]
+fn test_scripts_spawn_and_despawn_entities() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Position>().unwrap();
+    let world = helper.world_mut();
+
+    let doomed = world
+        .create_entity()
+        .with(Position { x: 0.0, y: 0.0 })
+        .build();
+
+    let source = MemorySource::new().with(
+        "spawner",
+        r#"
+        fn reads() { ["Position"] }
+        fn load() {}
+        fn update(delta) {
+            for entity in query("Position") {
+                despawn(entity);
+            }
+            spawn(#{ Position: #{ x: 1.0, y: 2.0 } });
+            spawn(#{ Position: Position(3.0, 4.0) });
+        }
+        "#,
+    );
+    let (scripts, errors) = source.load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+
+    let mut system = ScriptSystem::from_script(scripts.into_iter().next().unwrap(), world);
+    System::setup(&mut system, world);
+    system.run_now(world);
+    assert!(system.last_error().is_none());
+
+    // nothing changes until the world is maintained
+    assert!(world.is_alive(doomed));
+    assert_eq!(world.read_storage::<Position>().count(), 1);
+
+    world.maintain();
+    assert!(!world.is_alive(doomed));
+    let positions = world.read_storage::<Position>();
+    let mut spawned: Vec<_> = positions.join().map(|pos| (pos.x, pos.y)).collect();
+    spawned.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(spawned, [(1.0, 2.0), (3.0, 4.0)]);
+}
+
+#[test]
+fn test_script_instances_keep_per_entity_state() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Position>().unwrap();
+    let world = helper.world_mut();
+
+    let source = MemorySource::new()
+        .with(
+            "counter",
+            r#"
+            let count = 0;
+            fn writes() { ["Position"] }
+            fn load() {}
+            fn update(entity, delta) {
+                count += 1;
+                entity["Position"].x = count;
+            }
+            "#,
+        )
+        .with("other", "fn load() {} fn update(entity, delta) {}");
+    let (scripts, errors) = source.load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+
+    let mut systems: Vec<ScriptSystem> = scripts
+        .into_iter()
+        .map(|script| ScriptSystem::from_script(script, world))
+        .collect();
+    let counter = &mut systems[0];
+    System::setup(counter, world);
+
+    let first = world
+        .create_entity()
+        .with(Position { x: 0.0, y: 0.0 })
+        .with(ScriptInstance::new("counter"))
+        .build();
+    let unattached = world
+        .create_entity()
+        .with(Position { x: 0.0, y: 0.0 })
+        .with(ScriptInstance::new("other"))
+        .build();
+    counter.run_now(world);
+    counter.run_now(world);
+
+    let second = world
+        .create_entity()
+        .with(Position { x: 0.0, y: 0.0 })
+        .with(ScriptInstance::new("counter"))
+        .build();
+    counter.run_now(world);
+    assert!(counter.last_error().is_none());
+
+    // each entity counts its own updates
+    let positions = world.read_storage::<Position>();
+    assert_eq!(positions.get(first).unwrap().x, 3.0);
+    assert_eq!(positions.get(second).unwrap().x, 1.0);
+    assert_eq!(positions.get(unattached).unwrap().x, 0.0);
+
+    let instances = world.read_storage::<ScriptInstance>();
+    let count: i64 = instances
+        .get(first)
+        .unwrap()
+        .scope()
+        .get_value("count")
+        .unwrap();
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn test_components_fall_back_to_default_scripts() {
+    #[derive(Component, Scriptable, Clone, Debug)]
+    #[scriptable(category = "motion")]
+    struct Velocity {
+        x: f32,
+    }
+
+    #[derive(Component, Scriptable, Clone, Debug)]
+    struct Tag {
+        id: i32,
+    }
+
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let engine = Engine::new();
+    let (scripts, errors) = MemorySource::new()
+        .with("position", "const COMPONENT = \"Position\"; fn load() {}")
+        .with("motion", "fn load() {}")
+        .with("default", "fn load() {}")
+        .load_scripts(&engine);
+    assert!(errors.is_empty());
+    let mut scripts = scripts.into_iter();
+    let (default, motion) = (scripts.next().unwrap(), scripts.next().unwrap());
+    helper.add_scripts(scripts);
+
+    // nothing to fall back to yet
+    helper.register_scriptable::<Tag>().unwrap();
+    assert_eq!(helper.report().get("Tag"), Some(&ScriptBinding::Unbound));
+    assert!(helper.scripts_for::<Tag>().is_empty());
+
+    helper.set_default_script(default);
+    helper.set_category_script("motion", motion);
+    helper.register_scriptable::<Position>().unwrap();
+    helper.register_scriptable::<Velocity>().unwrap();
+    helper.register_scriptable::<Tag>().unwrap();
+
+    let report = helper.report();
+    assert_eq!(
+        report.get("Position"),
+        Some(&ScriptBinding::Bound {
+            scripts: vec!["position".to_owned()]
+        })
+    );
+    assert_eq!(
+        report.get("Velocity"),
+        Some(&ScriptBinding::Category {
+            category: "motion".to_owned(),
+            script: "motion".to_owned()
+        })
+    );
+    assert_eq!(
+        report.get("Tag"),
+        Some(&ScriptBinding::Default {
+            script: "default".to_owned()
+        })
+    );
+    let fallbacks: Vec<&str> = report.fallbacks().map(|(name, _)| name).collect();
+    assert_eq!(fallbacks, ["Velocity", "Tag"]);
+    assert_eq!(report.unbound().count(), 0);
+    assert_eq!(
+        helper.scripts_for::<Velocity>()[0].script().name(),
+        "motion"
+    );
+    assert!(report.to_string().contains("Tag -> default (default)"));
+}
+
+#[test]
+fn test_scripts_are_bound_explicitly() {
+    #[derive(Component, Scriptable, Clone, Debug)]
+    #[scriptable(script = "steering")]
+    struct Velocity {
+        x: f32,
+    }
+
+    #[derive(Component, Scriptable, Clone, Debug)]
+    struct Tag {
+        id: i32,
+    }
+
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let engine = Engine::new();
+    let (scripts, errors) = MemorySource::new()
+        .with("movement", "fn component() { \"Position\" } fn load() {}")
+        .with("steering", "fn load() {}")
+        .with("tag_a", "const COMPONENT = \"Tag\"; fn load() {}")
+        .with("tag_b", "const COMPONENT = \"Tag\"; fn load() {}")
+        .with("tagger", "fn load() {}")
+        .with("walker", "fn load() {} fn update(entity, delta) {}")
+        .load_scripts(&engine);
+    assert!(errors.is_empty());
+    helper.add_scripts(scripts);
+
+    // the script declares its component
+    helper.register_scriptable::<Position>().unwrap();
+    assert_eq!(
+        helper.scripts_for::<Position>()[0].script().name(),
+        "movement"
+    );
+
+    // the component names its script
+    helper.register_scriptable::<Velocity>().unwrap();
+    assert_eq!(
+        helper.scripts_for::<Velocity>()[0].script().name(),
+        "steering"
+    );
+
+    assert_eq!(
+        helper.register_scriptable::<Tag>(),
+        Err(BindingError::Ambiguous {
+            component: "Tag".to_owned(),
+            scripts: vec!["tag_a".to_owned(), "tag_b".to_owned()],
+        })
+    );
+    assert!(helper.scripts_for::<Tag>().is_empty());
+    assert_eq!(
+        helper.register_scriptable_with::<Tag>("nothing"),
+        Err(BindingError::MissingScript {
+            component: "Tag".to_owned(),
+            script: "nothing".to_owned(),
+        })
+    );
+    // a stack doesn't run scripts updating each entity on its own
+    assert_eq!(
+        helper.register_scriptable_with::<Tag>("walker"),
+        Err(BindingError::PerEntity {
+            component: "Tag".to_owned(),
+            script: "walker".to_owned(),
+        })
+    );
+
+    // an explicit binding wins over the scripts declaring the component
+    helper.register_scriptable_with::<Tag>("tag_b").unwrap();
+    let stack = helper.scripts_for::<Tag>();
+    let names: Vec<&str> = stack.iter().map(|bound| bound.script().name()).collect();
+    assert_eq!(names, ["tag_b"]);
+}
+
+#[test]
+fn test_components_stack_scripts_by_priority() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut log = rhai::Map::new();
+    log.insert("order".into(), Dynamic::from(String::new()));
+    helper.define_resource("Log", log).unwrap();
+
+    // every script adds its name to the log when it runs
+    let script = |header: &str, name: &str| {
+        format!(
+            r#"
+            {}
+            fn writes() {{ ["Log"] }}
+            fn load() {{}}
+            fn update(delta) {{
+                let log = resource_mut("Log");
+                log.order += "{} ";
+            }}
+            "#,
+            header, name
+        )
+    };
+    let (scripts, errors) = MemorySource::new()
+        .with("base", &script("const COMPONENT = \"Position\";", "base"))
+        .with(
+            "game_mode",
+            &script(
+                "const COMPONENT = \"Position\"; const PRIORITY = 10;",
+                "game_mode",
+            ),
+        )
+        .with(
+            "mod",
+            &script(
+                "fn component() { \"Position\" } fn priority() { -5 }",
+                "mod",
+            ),
+        )
+        .load_scripts(&helper.world().fetch::<Engine>());
+    assert!(errors.is_empty(), "{:?}", errors);
+    helper.add_scripts(scripts);
+    helper.register_scriptable::<Position>().unwrap();
+
+    let names = |helper: &WorldHelper| -> Vec<String> {
+        helper
+            .scripts_for::<Position>()
+            .enabled()
+            .map(|script| script.name().to_owned())
+            .collect()
+    };
+    assert_eq!(names(&helper), ["mod", "base", "game_mode"]);
+    assert_eq!(
+        helper.report().get("Position").unwrap().scripts(),
+        ["mod", "base", "game_mode"]
+    );
+
+    let mut system = helper.stack_system::<Position>();
+    System::setup(&mut system, helper.world_mut());
+    let mut run = |helper: &WorldHelper| -> String {
+        system.run_now(helper.world());
+        assert!(system.errors().is_empty(), "{:?}", system.errors());
+        let mut log = fetch_resource_mut(helper.world(), "Log").unwrap();
+        let order: String = log.value("order").unwrap();
+        log.set_field("order", Dynamic::from(String::new()))
+            .unwrap();
+        order
+    };
+    assert_eq!(run(&helper), "mod base game_mode ");
+
+    // the system follows the stack as it changes
+    assert!(helper.set_script_enabled::<Position>("game_mode", false));
+    assert!(!helper.set_script_enabled::<Position>("missing", false));
+    assert_eq!(names(&helper), ["mod", "base"]);
+    assert_eq!(helper.scripts_for::<Position>().len(), 3);
+    assert_eq!(run(&helper), "mod base ");
+
+    assert!(helper.set_script_enabled::<Position>("game_mode", true));
+    assert!(helper.set_script_priority::<Position>("mod", 20));
+    assert_eq!(names(&helper), ["base", "game_mode", "mod"]);
+    assert_eq!(run(&helper), "base game_mode mod ");
+}
+
+#[test]
+fn test_reflect_scriptable_components() {
+    #[derive(Component, Scriptable, Clone, Debug)]
+    #[scriptable(rename = "Hp")]
+    struct Health {
+        #[scriptable(rename = "hp")]
+        current: i32,
+        #[scriptable(readonly)]
+        max: i32,
+        #[scriptable(skip)]
+        regen_timer: f32,
+    }
+
+    let mut health = Health {
+        current: 5,
+        max: 10,
+        regen_timer: 1.5,
+    };
+    let fields: Vec<(&str, &str)> = health
+        .fields()
+        .iter()
+        .map(|field| (field.name, field.type_name))
+        .collect();
+    assert_eq!(fields, [("hp", "i32"), ("max", "i32")]);
+
+    assert_eq!(health.get_field("hp").unwrap().as_int(), Ok(5));
+    health.set_field("hp", Dynamic::from(7_i64)).unwrap();
+    assert_eq!(health.current, 7);
+    assert_eq!(
+        health.set_field("max", Dynamic::from(1_i64)),
+        Err(ReflectError::ReadOnly {
+            component: "Hp".to_owned(),
+            field: "max".to_owned()
+        })
+    );
+    assert!(matches!(
+        health.get_field("regen_timer"),
+        Err(ReflectError::UnknownField { .. })
+    ));
+    assert!(matches!(
+        health.set_field("hp", Dynamic::from("lots")),
+        Err(ReflectError::InvalidValue { .. })
+    ));
+
+    let map = health.to_map();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["max"].as_int(), Ok(10));
+    let copy = Health::from_map(map).unwrap();
+    assert_eq!((copy.current, copy.max, copy.regen_timer), (7, 10, 0.0));
+
+    let mut map = health.to_map();
+    map.remove("max");
+    assert_eq!(
+        Health::from_map(map.clone()).unwrap_err(),
+        ReflectError::MissingField {
+            component: "Hp".to_owned(),
+            field: "max".to_owned()
+        }
+    );
+    map.insert("max".into(), Dynamic::from(3_i64));
+    map.insert("shield".into(), Dynamic::from(3_i64));
+    assert!(matches!(
+        Health::from_map(map),
+        Err(ReflectError::UnknownField { field, .. }) if field == "shield"
+    ));
+
+    // generic code can reach any registered resource through the reflection table
+    let mut world: World = WorldExt::new();
+    let mut table = ReflectionTable::new();
+    table.register(&Position { x: 0.0, y: 0.0 });
+    world.insert(table);
+    world.insert(Position { x: 1.0, y: 2.0 });
+
+    let table = world.fetch::<ReflectionTable>();
+    let reflected: Vec<_> = table
+        .iter(&world)
+        .map(|component| component.to_map())
+        .collect();
+    assert_eq!(reflected.len(), 1);
+    assert_eq!(reflected[0]["y"].as_float(), Ok(2.0));
+    for component in table.iter_mut(&world) {
+        component.set_fields(reflected[0].clone()).unwrap();
+        component.set_field("x", Dynamic::from(4.0)).unwrap();
+    }
+    drop(table);
+    assert_eq!(world.fetch::<Position>().x, 4.0);
+}
+
+#[test]
+fn test_call_component_methods() {
+    #[derive(Component, Scriptable, Clone, Debug)]
+    #[scriptable(methods)]
+    struct Health {
+        hp: i32,
+        max: i32,
+    }
+
+    #[script_methods]
+    impl Health {
+        fn heal(&mut self, amount: i32) {
+            self.hp = (self.hp + amount).min(self.max);
+        }
+
+        #[scriptable(rename = "dead")]
+        fn is_dead(&self) -> bool {
+            self.hp <= 0
+        }
+
+        #[scriptable(skip)]
+        #[allow(dead_code)]
+        fn kill(&mut self) {
+            self.hp = 0;
+        }
+    }
+
+    let mut health = Health { hp: 0, max: 10 };
+    let names: Vec<(&str, bool)> = health
+        .methods()
+        .iter()
+        .map(|method| (method.name, method.mutable))
+        .collect();
+    assert_eq!(names, [("heal", true), ("dead", false)]);
+
+    let component: &dyn ScriptableComponent = &health;
+    assert_eq!(
+        component.call_method("dead", &[]).unwrap().as_bool(),
+        Ok(true)
+    );
+    assert!(matches!(
+        component.call_method("heal", &[Dynamic::from(5_i64)]),
+        Err(ReflectError::Immutable { .. })
+    ));
+
+    let component: &mut dyn ScriptableComponent = &mut health;
+    component
+        .call_method_mut("heal", &[Dynamic::from(5_i64)])
+        .unwrap();
+    assert_eq!(
+        component.call_method_mut("dead", &[]).unwrap().as_bool(),
+        Ok(false)
+    );
+    assert_eq!(
+        component.call_method_mut("heal", &[]).unwrap_err(),
+        ReflectError::WrongArgumentCount {
+            component: "Health".to_owned(),
+            method: "heal".to_owned(),
+            expected: 1,
+            found: 0
+        }
+    );
+    assert!(matches!(
+        component.call_method_mut("heal", &[Dynamic::from("a lot")]),
+        Err(ReflectError::InvalidArgument { index: 0, .. })
+    ));
+    assert!(matches!(
+        component.call_method_mut("kill", &[]),
+        Err(ReflectError::UnknownMethod { .. })
+    ));
+    assert_eq!(health.hp, 5);
+
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Health>().unwrap();
+    let world = helper.world_mut();
+    let entity = world
+        .create_entity()
+        .with(Health { hp: 1, max: 10 })
+        .build();
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "healer",
+            r#"
+            fn writes() { ["Health"] }
+            fn load() {}
+            fn update(delta) {
+                for entity in query("Health") {
+                    entity["Health"].invoke("heal", [5]);
+                    entity["Health"].heal(15);
+                    if entity["Health"].dead() { throw "still dead"; }
+                    if entity["Health"].invoke("dead", []) { throw "still dead"; }
+                }
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+    let mut system = ScriptSystem::from_script(scripts.into_iter().next().unwrap(), world);
+    System::setup(&mut system, world);
+    system.run_now(world);
+    assert!(system.last_error().is_none(), "{:?}", system.last_error());
+    assert_eq!(world.read_storage::<Health>().get(entity).unwrap().hp, 10);
+}
+
+#[test]
+fn test_scripts_use_resources() {
+    #[derive(Scriptable, Clone, Debug)]
+    struct GameTime {
+        ticks: i64,
+    }
+
+    #[derive(Scriptable, Clone, Debug)]
+    struct Score {
+        points: i64,
+    }
+
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.insert_scriptable_resource(GameTime { ticks: 3 });
+    helper.insert_scriptable_resource(Score { points: 0 });
+    let world = helper.world_mut();
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "scorer",
+            r#"
+            fn reads() { ["GameTime"] }
+            fn writes() { ["Score"] }
+            fn load() {}
+            fn update(delta) {
+                let score = resource_mut("Score");
+                score.points += resource("GameTime").ticks;
+                score["points"] += 1;
+                if score.to_map().points != 4 { throw "not written"; }
+            }
+            "#,
+        )
+        .with(
+            "cheater",
+            r#"
+            fn reads() { ["Score"] }
+            fn load() {}
+            fn update(delta) {
+                let score = resource("Score");
+                score.points = 1000;
+            }
+            "#,
+        )
+        .with(
+            "greedy",
+            r#"
+            fn reads() { ["Score"] }
+            fn load() {}
+            fn update(delta) {
+                resource_mut("Score");
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+
+    let mut systems: Vec<ScriptSystem> = scripts
+        .into_iter()
+        .map(|script| ScriptSystem::from_script(script, world))
+        .collect();
+    for system in &mut systems {
+        System::setup(system, world);
+        system.run_now(world);
+    }
+
+    // cheater, greedy, scorer
+    assert!(
+        systems[2].last_error().is_none(),
+        "{:?}",
+        systems[2].last_error()
+    );
+    assert_eq!(world.fetch::<Score>().points, 4);
+    let error = systems[0].last_error().unwrap().to_string();
+    assert!(
+        error.contains("use `resource_mut` to change it"),
+        "{}",
+        error
+    );
+    let error = systems[1].last_error().unwrap().to_string();
+    assert!(
+        error.contains("did not declare that it writes `Score`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_resource_table_allocates_dynamic_ids() {
+    let mut world: World = WorldExt::new();
+    let mut table = ResourceTable::new();
+
+    let first = table.register_dynamic::<i32>("first").unwrap();
+    let second = table.register_dynamic::<i32>("second").unwrap();
+    assert_ne!(first, second);
+    assert_eq!(
+        table.register_dynamic::<i32>("first"),
+        Err(ResourceTableError::AlreadyRegistered("first".to_owned()))
+    );
+
+    let first_id = first.clone();
+    world.insert_by_id(first, 1i32);
+    world.insert_by_id(second, 2i32);
+    let id = table.get("first").unwrap();
+    assert_eq!(*world.try_fetch_by_id::<i32>(id).unwrap(), 1);
+
+    table.rename("second", "renamed").unwrap();
+    assert_eq!(table.get("second"), None);
+    let id = table.get("renamed").unwrap();
+    assert_eq!(*world.try_fetch_by_id::<i32>(id).unwrap(), 2);
+    assert_eq!(
+        table.rename("second", "other"),
+        Err(ResourceTableError::Unknown("second".to_owned()))
+    );
+    assert_eq!(
+        table.rename("first", "renamed"),
+        Err(ResourceTableError::AlreadyRegistered("renamed".to_owned()))
+    );
+
+    // names are only found as the type they were registered with
+    table.register::<Time>("Time");
+    assert_eq!(table.get_as::<i32>("first"), Some(first_id));
+    assert_eq!(table.get_as::<Time>("first"), None);
+    assert_eq!(table.get_as::<i32>("Time"), None);
+
+    assert!(table.unregister("first").is_some());
+    assert!(!table.contains("first"));
+    assert_eq!(table.unregister("first"), None);
+}
+
+#[test]
+fn test_scripts_define_resources() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut weather = rhai::Map::new();
+    weather.insert("rain".into(), Dynamic::from(0.0 as rhai::FLOAT));
+    weather.insert("wind".into(), Dynamic::from(3 as rhai::INT));
+    helper.define_resource("Weather", weather.clone()).unwrap();
+    assert_eq!(
+        helper.define_resource("Weather", weather),
+        Err(ResourceTableError::AlreadyRegistered("Weather".to_owned()))
+    );
+    let world = helper.world_mut();
+
+    let engine = world.fetch::<Engine>();
+    let source = MemorySource::new()
+        .with(
+            "forecast",
+            r#"
+            fn writes() { ["Weather"] }
+            fn load() {}
+            fn update(delta) {
+                let weather = resource_mut("Weather");
+                weather.rain += 0.5;
+                weather.wind = weather.wind * 2;
+                define_resource("Season", #{name: "spring", day: 1});
+            }
+            "#,
+        )
+        .with(
+            "harvest",
+            r#"
+            fn load() {}
+            fn update(delta) {
+                define_resource("Season", #{});
+            }
+            "#,
+        )
+        .with(
+            "storm",
+            r#"
+            fn writes() { ["Weather"] }
+            fn load() {}
+            fn update(delta) {
+                let weather = resource_mut("Weather");
+                weather.wind = "strong";
+            }
+            "#,
+        )
+        .with(
+            "calendar",
+            r#"
+            fn writes() { ["Season"] }
+            fn load() {}
+            fn update(delta) {
+                let season = resource_mut("Season");
+                season.day += 1;
+                define_resource("Weather", #{});
+            }
+            "#,
+        );
+    let (scripts, errors) = source.load_scripts(&engine);
+    drop(engine);
+    assert!(errors.is_empty());
+
+    let mut scripts = scripts.into_iter();
+    let calendar = scripts.next().unwrap();
+    for script in scripts {
+        let mut system = ScriptSystem::from_script(script, world);
+        System::setup(&mut system, world);
+        system.run_now(world);
+        if system.script().name() == "storm" {
+            let error = system.last_error().unwrap().to_string();
+            assert!(error.contains("expected i64, got string"), "{}", error);
+        } else if system.script().name() == "harvest" {
+            // `forecast` defined it earlier in the same frame
+            let error = system.last_error().unwrap().to_string();
+            assert!(error.contains("`Season` already exists"), "{}", error);
+        } else {
+            assert!(system.last_error().is_none(), "{:?}", system.last_error());
+        }
+    }
+
+    // `Season` is added when the world is maintained
+    assert!(fetch_resource(world, "Season").is_none());
+    // names of other kinds of resources aren't runtime resources
+    world.insert(Time::default());
+    world
+        .write_resource::<ResourceTable>()
+        .register::<Time>("Time");
+    assert!(fetch_resource(world, "Time").is_none());
+    assert!(fetch_resource_mut(world, "Time").is_none());
+    world.maintain();
+    {
+        let weather = fetch_resource(world, "Weather").unwrap();
+        assert_eq!(weather.value::<rhai::FLOAT>("rain"), Some(0.5));
+        assert_eq!(weather.value::<rhai::INT>("wind"), Some(6));
+    }
+
+    let mut system = ScriptSystem::from_script(calendar, world);
+    System::setup(&mut system, world);
+    system.run_now(world);
+    let error = system.last_error().unwrap().to_string();
+    assert!(error.contains("`Weather` already exists"), "{}", error);
+
+    let season = fetch_resource(world, "Season").unwrap();
+    assert_eq!(season.value::<rhai::INT>("day"), Some(2));
+    assert_eq!(
+        season.value::<rhai::ImmutableString>("name").unwrap(),
+        "spring"
+    );
+    assert_eq!(season.name(), "Season");
+    drop(season);
+
+    // adding it can still fail when the world is maintained, the script's system
+    // reports that on its next run
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "climate",
+            r#"
+            let defined = false;
+            fn load() {}
+            fn update(delta) {
+                if !defined { define_resource("Climate", #{}); }
+                defined = true;
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+    let mut system = script_systems(scripts, world).remove(0);
+    system.run_now(world);
+    assert_ran(&system);
+    world
+        .write_resource::<ResourceTable>()
+        .register::<Time>("Climate");
+    world.maintain();
+    system.run_now(world);
+    let error = system.last_error().unwrap().to_string();
+    assert!(
+        error.contains("couldn't add the resource `Climate`"),
+        "{}",
+        error
+    );
+    system.run_now(world);
+    assert_ran(&system);
+}
+
+#[test]
+fn test_scripts_register_components() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut schema = rhai::Map::new();
+    schema.insert("hp".into(), Dynamic::from(100 as rhai::INT));
+    schema.insert("max".into(), Dynamic::from(100 as rhai::INT));
+    helper.register_component("Health", schema).unwrap();
+    let world = helper.world_mut();
+
+    let hurt = world.create_entity().build();
+    {
+        let mut health = fetch_dynamic_storage_mut(world, "Health").unwrap();
+        let mut fields = rhai::Map::new();
+        fields.insert("hp".into(), Dynamic::from(20 as rhai::INT));
+        let component = health.build(fields).unwrap();
+        health.insert(hurt, component);
+    }
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "healer",
+            r#"
+            fn writes() { ["Health"] }
+            fn load() {}
+            fn update(delta) {
+                for entity in query("Health") {
+                    entity["Health"].hp += 10;
+                }
+                spawn(#{ Health: #{hp: 50} });
+                register_component("Shield", #{amount: 5});
+            }
+            "#,
+        )
+        .with(
+            "poisoner",
+            r#"
+            fn writes() { ["Health"] }
+            fn load() {}
+            fn update(delta) {
+                for entity in query("Health") {
+                    entity["Health"] = #{hp: 1.5};
+                }
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+
+    let mut systems: Vec<ScriptSystem> = scripts
+        .into_iter()
+        .map(|script| ScriptSystem::from_script(script, world))
+        .collect();
+    for system in &mut systems {
+        System::setup(system, world);
+        system.run_now(world);
+    }
+    world.maintain();
+
+    assert!(
+        systems[0].last_error().is_none(),
+        "{:?}",
+        systems[0].last_error()
+    );
+    let error = systems[1].last_error().unwrap().to_string();
+    assert!(error.contains("expected i64, got f64"), "{}", error);
+
+    let health = fetch_dynamic_storage(world, "Health").unwrap();
+    let healed = health.get(hurt).unwrap();
+    assert_eq!(healed.value::<rhai::INT>("hp"), Some(30));
+    assert_eq!(healed.value::<rhai::INT>("max"), Some(100));
+    let spawned: Vec<_> = health
+        .iter()
+        .filter(|(entity, _)| entity.id() != 0)
+        .map(|(_, health)| health.value::<rhai::INT>("hp").unwrap())
+        .collect();
+    assert_eq!(spawned, vec![50]);
+
+    let shield = fetch_dynamic_storage(world, "Shield").unwrap();
+    assert_eq!(shield.schema().len(), 1);
+    assert!(shield.iter().next().is_none());
+    drop((health, shield));
+
+    // components of deleted entities are gone once the world is maintained
+    world.delete_entity(hurt).unwrap();
+    world.maintain();
+    {
+        let health = fetch_dynamic_storage(world, "Health").unwrap();
+        assert!(health.get(hurt).is_none());
+        assert_eq!(health.iter().count(), 1);
+    }
+    assert!(fetch_dynamic_storage_mut(world, "Health")
+        .unwrap()
+        .remove(hurt)
+        .is_none());
+
+    // names of other kinds of resources aren't dynamic components
+    world.insert(Time::default());
+    world
+        .write_resource::<ResourceTable>()
+        .register::<Time>("Time");
+    assert!(fetch_dynamic_storage(world, "Time").is_none());
+    assert!(fetch_dynamic_storage_mut(world, "Time").is_none());
+}
+
+#[test]
+fn test_native_systems_write_runtime_resources() {
+    struct Windmill {
+        accessor: ScriptingResAccessor,
+    }
+
+    impl<'a> System<'a> for Windmill {
+        type SystemData = ScriptingResData<'a>;
+
+        fn run(&mut self, mut data: Self::SystemData) {
+            let rain = data.read("Weather").unwrap().value::<rhai::FLOAT>("rain");
+            assert_eq!(rain, Some(0.5));
+            assert!(data.write("Weather").is_none());
+
+            let energy = data.write("Energy").unwrap();
+            let stored = energy.value::<rhai::INT>("stored").unwrap();
+            energy
+                .set_field("stored", Dynamic::from(stored + 2))
+                .unwrap();
+        }
+
+        fn accessor<'b>(&'b self) -> AccessorCow<'a, 'b, Self> {
+            AccessorCow::Ref(&self.accessor)
+        }
+    }
+
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut weather = rhai::Map::new();
+    weather.insert("rain".into(), Dynamic::from(0.5 as rhai::FLOAT));
+    helper.define_resource("Weather", weather).unwrap();
+    let mut energy = rhai::Map::new();
+    energy.insert("stored".into(), Dynamic::from(0 as rhai::INT));
+    helper.define_resource("Energy", energy).unwrap();
+    let world = helper.world_mut();
+
+    assert_eq!(
+        ScriptingResAccessor::new(&["Sunshine"], &[], world).unwrap_err(),
+        ResourceTableError::Unknown("Sunshine".to_owned())
+    );
+    world.insert(Time::default());
+    world
+        .write_resource::<ResourceTable>()
+        .register::<Time>("Time");
+    assert_eq!(
+        ScriptingResAccessor::new(&[], &["Time"], world).unwrap_err(),
+        ResourceTableError::WrongKind("Time".to_owned())
+    );
+    // a world without a resource table has no runtime resources
+    let empty: World = WorldExt::new();
+    assert_eq!(
+        ScriptingResAccessor::new(&["Weather"], &[], &empty).unwrap_err(),
+        ResourceTableError::Unknown("Weather".to_owned())
+    );
+    let accessor = ScriptingResAccessor::new(&["Weather"], &["Energy"], world).unwrap();
+    {
+        let table = world.fetch::<ResourceTable>();
+        assert_eq!(accessor.reads(), vec![table.get("Weather").unwrap()]);
+        assert_eq!(accessor.writes(), vec![table.get("Energy").unwrap()]);
+    }
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "meter",
+            r#"
+            fn reads() { ["Energy"] }
+            fn load() {}
+            fn update(delta) {
+                if resource("Energy").stored > 6 { throw "too much energy"; }
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+    let meter = ScriptSystem::from_script(scripts.into_iter().next().unwrap(), world);
+
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(Windmill { accessor }, "windmill", &[])
+        .with(meter, "meter", &[])
+        .build();
+    dispatcher.setup(world);
+    for _ in 0..3 {
+        dispatcher.dispatch(world);
+    }
+
+    let energy = fetch_resource(world, "Energy").unwrap();
+    assert_eq!(energy.value::<rhai::INT>("stored"), Some(6));
+}
+
+#[test]
+fn test_scripts_hook_into_component_lifecycle() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut log = rhai::Map::new();
+    for field in ["added", "modified", "removed", "seen"] {
+        log.insert(field.into(), Dynamic::from(0 as rhai::INT));
+    }
+    helper.define_resource("Log", log).unwrap();
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "tracker",
+            r#"
+            const COMPONENT = "Position";
+            const PRIORITY = 1;
+            let seen = 0;
+            fn writes() { ["Log", "Position"] }
+            fn load() {}
+            fn update(delta) {
+                let log = resource_mut("Log");
+                log.seen = seen;
+            }
+            fn on_added(entity) {
+                let log = resource_mut("Log");
+                log.added += 1;
+                seen += 1;
+                entity["Position"].y = entity["Position"].x;
+            }
+            fn on_modified(entity) {
+                let log = resource_mut("Log");
+                log.modified += 1;
+            }
+            fn on_removed(entity) {
+                if entity.has("Position") { throw "still there"; }
+                let log = resource_mut("Log");
+                log.removed += 1;
+            }
+            "#,
+        )
+        .with("plain", "const COMPONENT = \"Position\"; fn load() {}")
+        .load_scripts(&helper.world().fetch::<Engine>());
+    assert!(errors.is_empty());
+    helper.add_scripts(scripts);
+    helper.register_scriptable::<Position>().unwrap();
+
+    let mut system = helper.lifecycle_system::<Position>().unwrap();
+    let mut updates = helper.stack_system::<Position>();
+    let world = helper.world_mut();
+    System::setup(&mut system, world);
+    System::setup(&mut updates, world);
+
+    let log = |world: &World| -> Vec<rhai::INT> {
+        let log = fetch_resource(world, "Log").unwrap();
+        ["added", "modified", "removed"]
+            .iter()
+            .map(|field| log.value(field).unwrap())
+            .collect()
+    };
+
+    let entity = world
+        .create_entity()
+        .with(Position { x: 4.0, y: 0.0 })
+        .build();
+    system.run_now(world);
+    assert!(system.errors().is_empty(), "{:?}", system.errors());
+    assert_eq!(log(world), [1, 0, 0]);
+    assert_eq!(world.read_storage::<Position>().get(entity).unwrap().y, 4.0);
+
+    // the hooks and the update phases share the script's scope
+    updates.run_now(world);
+    assert!(updates.errors().is_empty(), "{:?}", updates.errors());
+    let seen = fetch_resource(world, "Log")
+        .unwrap()
+        .value::<rhai::INT>("seen");
+    assert_eq!(seen, Some(1));
+
+    // the hook changed the position
+    system.run_now(world);
+    assert_eq!(log(world), [1, 1, 0]);
+    system.run_now(world);
+    assert_eq!(log(world), [1, 1, 0]);
+
+    world.delete_entity(entity).unwrap();
+    world.maintain();
+    system.run_now(world);
+    assert!(system.errors().is_empty(), "{:?}", system.errors());
+    assert_eq!(log(world), [1, 1, 1]);
+}
+
+#[test]
+fn test_scripts_query_changed_entities() {
+    #[derive(Component, Scriptable, Clone, Debug)]
+    struct Speed {
+        value: f32,
+    }
+
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.register_scriptable::<Position>().unwrap();
+    helper.track_changes::<Position>();
+    helper.register_scriptable::<Speed>().unwrap();
+    define_log(&mut helper, &["changed"]);
+    let world = helper.world_mut();
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "watcher",
+            r#"
+            fn reads() { ["Position"] }
+            fn writes() { ["Log"] }
+            fn load() {}
+            fn update(delta) {
+                let log = resource_mut("Log");
+                log.changed = query_changed("Position").len();
+            }
+            "#,
+        )
+        .with(
+            "untracked",
+            r#"
+            fn reads() { ["Speed"] }
+            fn load() {}
+            fn update(delta) {
+                query_changed(["Speed"]);
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+    let mut systems = script_systems(scripts, world);
+
+    let entities: Vec<Entity> = (0..3)
+        .map(|i| {
+            world
+                .create_entity()
+                .with(Position {
+                    x: i as f32,
+                    y: 0.0,
+                })
+                .build()
+        })
+        .collect();
+    let mut run = |world: &mut World| -> rhai::INT {
+        for system in &mut systems {
+            system.run_now(world);
+        }
+        fetch_resource(world, "Log")
+            .unwrap()
+            .value("changed")
+            .unwrap()
+    };
+
+    assert_eq!(run(world), 3);
+    assert_eq!(run(world), 0);
+    world
+        .write_storage::<Position>()
+        .get_mut(entities[1])
+        .unwrap()
+        .x = 10.0;
+    assert_eq!(run(world), 1);
+    assert_eq!(run(world), 0);
+
+    // untracked, watcher
+    let error = systems[0].last_error().unwrap().to_string();
+    assert!(
+        error.contains("changes to `Speed` aren't tracked"),
+        "{}",
+        error
+    );
+    assert_ran(&systems[1]);
+}
+
+#[test]
+fn test_stacked_scripts_query_changed_entities() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    define_log(&mut helper, &["first", "second"]);
+
+    // each script logs how many positions changed since it last ran
+    let script = |field: &str, priority: i32| {
+        format!(
+            r#"
+            const COMPONENT = "Position";
+            const PRIORITY = {};
+            fn reads() {{ ["Position"] }}
+            fn writes() {{ ["Log"] }}
+            fn load() {{}}
+            fn update(delta) {{
+                let log = resource_mut("Log");
+                log.{} = query_changed("Position").len();
+            }}
+            "#,
+            priority, field
+        )
+    };
+    let (scripts, errors) = MemorySource::new()
+        .with("first", &script("first", 0))
+        .with("second", &script("second", 1))
+        .load_scripts(&helper.world().fetch::<Engine>());
+    assert!(errors.is_empty(), "{:?}", errors);
+    helper.add_scripts(scripts);
+    helper.register_scriptable::<Position>().unwrap();
+    helper.track_changes::<Position>();
+
+    let mut system = helper.stack_system::<Position>();
+    System::setup(&mut system, helper.world_mut());
+    let entities: Vec<Entity> = (0..3)
+        .map(|i| {
+            helper
+                .world_mut()
+                .create_entity()
+                .with(Position {
+                    x: i as f32,
+                    y: 0.0,
+                })
+                .build()
+        })
+        .collect();
+    let mut run = |helper: &WorldHelper| -> (rhai::INT, rhai::INT) {
+        system.run_now(helper.world());
+        assert!(system.errors().is_empty(), "{:?}", system.errors());
+        let log = fetch_resource(helper.world(), "Log").unwrap();
+        (log.value("first").unwrap(), log.value("second").unwrap())
+    };
+
+    assert_eq!(run(&helper), (3, 3));
+    assert_eq!(run(&helper), (0, 0));
+
+    // a disabled script catches up on the changes once it's enabled again
+    helper.set_script_enabled::<Position>("second", false);
+    helper
+        .world()
+        .write_storage::<Position>()
+        .get_mut(entities[1])
+        .unwrap()
+        .x = 10.0;
+    assert_eq!(run(&helper), (1, 0));
+    helper.set_script_enabled::<Position>("second", true);
+    assert_eq!(run(&helper), (0, 1));
+}
+
+#[test]
+fn test_scripts_run_update_phases() {
+    let engine = Engine::new();
+    let (mut scripts, errors) = MemorySource::new()
+        .with(
+            "phases",
+            r#"
+            let order = "";
+            fn load() {}
+            fn fixed_update(step) { order += "f"; }
+            fn update(delta) { order += "u"; }
+            fn late_update(delta) { order += "l"; }
+            "#,
+        )
+        .with("idle", "fn load() {}")
+        .load_scripts(&engine);
+    assert!(errors.is_empty());
+
+    // idle, phases
+    scripts[0].update(&engine).unwrap();
+    let phases = &mut scripts[1];
+    phases.set_fixed_step(0.1);
+    let frame = phases.frame(0.25);
+    assert_eq!(frame.steps, 2);
+    phases.run_frame(&engine, frame).unwrap();
+    let order: rhai::ImmutableString = phases.scope.get_value("order").unwrap();
+    assert_eq!(order, "fful");
+
+    // the leftover 0.05 adds up with the next frame
+    assert_eq!(phases.frame(0.06).steps, 1);
+    // a long frame runs a bounded number of steps and drops the rest
+    assert_eq!(phases.frame(10.0).steps, 8);
+    assert_eq!(phases.frame(0.0).steps, 0);
+}
+
+#[test]
+fn test_scripts_unload_on_reload_and_removal() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    define_log(&mut helper, &["unloads", "updates"]);
+    let world = helper.world_mut();
+
+    let text = r#"
+        fn writes() { ["Log"] }
+        fn load() {}
+        fn update(delta) {
+            let log = resource_mut("Log");
+            log.updates += 1;
+        }
+        fn unload() {
+            let log = resource_mut("Log");
+            log.unloads += 1;
+        }
+    "#;
+    let (scripts, errors) = MemorySource::new()
+        .with("unloader", text)
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+    let mut system = ScriptSystem::from_script(scripts.into_iter().next().unwrap(), world);
+    System::setup(&mut system, world);
+
+    let log = |world: &World| -> (rhai::INT, rhai::INT) {
+        let log = fetch_resource(world, "Log").unwrap();
+        (log.value("unloads").unwrap(), log.value("updates").unwrap())
+    };
+    let publish = |world: &mut World| {
+        let ast = world.fetch::<Engine>().compile(text).unwrap();
+        world.fetch_mut::<ScriptLibrary>().publish("unloader", ast);
+    };
+
+    system.run_now(world);
+    assert_eq!(log(world), (0, 1));
+
+    publish(world);
+    system.run_now(world);
+    assert_ran(&system);
+    assert_eq!(log(world), (1, 2));
+
+    world.fetch_mut::<ScriptLibrary>().remove("unloader");
+    system.run_now(world);
+    system.run_now(world);
+    assert_eq!(log(world), (2, 2));
+
+    // publishing it again brings it back without unloading it twice
+    publish(world);
+    system.run_now(world);
+    assert_eq!(log(world), (2, 3));
+}
+
+#[test]
+fn test_scripts_share_time() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut time = Time::default();
+    time.fixed_step = 0.125;
+    helper.insert_scriptable_resource(time);
+    let world = helper.world_mut();
+
+    let clock = r#"
+        let fixed = 0;
+        let elapsed = 0.0;
+        fn reads() { ["Time"] }
+        fn load() {}
+        fn fixed_update(step) { fixed += 1; }
+        fn update(delta) {
+            if resource("Time").delta != delta { throw "drifted"; }
+            elapsed += delta;
+        }
+    "#;
+    let (mut scripts, errors) = MemorySource::new()
+        .with("clock", clock)
+        .with("metronome", clock)
+        .with(
+            "director",
+            r#"
+            fn writes() { ["Time"] }
+            fn load() {}
+            fn update(delta) {
+                let time = resource_mut("Time");
+                if time.tick == 1 { time.scale = 2.0; }
+                if time.tick == 2 { time.paused = true; }
+                if time.tick == 3 { time.tick = 0; }
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty());
+    // a step of its own wins over the one of `Time`
+    scripts[2].set_fixed_step(0.25);
+    scripts[2].set_fixed_step(0.0);
+    assert_eq!(scripts[2].fixed_step(), 0.25);
+    let mut systems: Vec<ScriptSystem> = scripts
+        .into_iter()
+        .map(|script| ScriptSystem::from_script(script, world))
+        .collect();
+    for system in &mut systems {
+        System::setup(system, world);
+    }
+
+    for _ in 0..3 {
+        world.write_resource::<Time>().advance_by(0.25);
+        for system in &mut systems {
+            system.run_now(world);
+        }
+    }
+
+    // clock, director, metronome
+    assert!(
+        systems[0].last_error().is_none(),
+        "{:?}",
+        systems[0].last_error()
+    );
+    let error = systems[1].last_error().unwrap().to_string();
+    assert!(error.contains("read only"), "{}", error);
+
+    let time = Time::clone(&world.read_resource::<Time>());
+    assert_eq!((time.delta, time.total, time.tick), (0.0, 0.75, 3));
+    assert!(time.paused);
+    let scope = &systems[0].script().scope;
+    assert_eq!(scope.get_value::<f64>("elapsed"), Some(0.75));
+    assert_eq!(scope.get_value::<rhai::INT>("fixed"), Some(6));
+    let scope = &systems[2].script().scope;
+    assert_eq!(systems[2].script().fixed_step(), 0.25);
+    assert_eq!(scope.get_value::<rhai::INT>("fixed"), Some(3));
+
+    world.write_resource::<Time>().paused = false;
+    TimeSystem.run_now(world);
+    assert_eq!(world.read_resource::<Time>().tick, 4);
+}
+
+#[test]
+fn test_scripts_are_held_to_their_limits() {
+    // no global limits, the world API holds scripts to their own
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let mut counter = rhai::Map::new();
+    counter.insert("updates".into(), Dynamic::from(0 as rhai::INT));
+    helper.define_resource("Counter", counter).unwrap();
+    let world = helper.world_mut();
+
+    // sorted by name, the systems are created in this order
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "a_spinner",
+            r#"
+            const LIMITS = #{max_operations: 1000};
+            fn load() {}
+            fn update(delta) { loop {} }
+            "#,
+        )
+        .with(
+            "b_hoarder",
+            r#"
+            fn limits() { #{max_variables: 4} }
+            fn load() {}
+            fn update(delta) { let a = 1; let b = 2; let c = 3; let d = 4; let e = 5; }
+            "#,
+        )
+        .with(
+            "c_counter",
+            r#"
+            fn writes() { ["Counter"] }
+            fn load() {}
+            fn update(delta) {
+                let counter = resource_mut("Counter");
+                counter.updates += 1;
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut systems = script_systems(scripts, world);
+
+    for _ in 0..10 {
+        for system in &mut systems {
+            system.run_now(world);
+        }
+    }
+
+    assert_eq!(exceeded_limit(&systems[0]), "operations");
+    assert_eq!(exceeded_limit(&systems[1]), "variables");
+    for system in &systems[..2] {
+        assert!(system.script().is_disabled());
+    }
+
+    // the well behaved script kept running alongside them
+    assert_ran(&systems[2]);
+    assert!(!systems[2].script().is_disabled());
+    let counter = fetch_resource(world, "Counter").unwrap();
+    assert_eq!(counter.value::<rhai::INT>("updates"), Some(10));
+
+    // a manifest with an unknown limit doesn't load
+    let err = MemorySource::new()
+        .with("typo", "const LIMITS = #{max_operation: 10}; fn load() {}")
+        .load_scripts(&world.fetch::<Engine>())
+        .1
+        .remove(0);
+    assert!(
+        err.to_string().contains("unknown limit `max_operation`"),
+        "{}",
+        err
+    );
+
+    // the top level statements are held to the limits too
+    let err = MemorySource::new()
+        .with(
+            "busy",
+            r#"
+            const LIMITS = #{max_operations: 100};
+            let i = 0;
+            while i < 100000 { i += 1; }
+            fn load() {}
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>())
+        .1
+        .remove(0);
+    assert!(matches!(&err, ScriptError::LimitExceeded { limit, .. } if limit == "operations"));
+}
+
+#[test]
+fn test_scripts_are_held_to_their_own_sizes_and_call_depth() {
+    // no global limits, each of these only trips the limit it declares
+    let mut helper = WorldHelper::new(WorldExt::new());
+    let world = helper.world_mut();
+    world.insert(ResourceTable::new());
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "a_recurser",
+            r#"
+            const LIMITS = #{max_call_depth: 4};
+            fn load() {}
+            fn down() { down() }
+            fn update(delta) { down(); }
+            "#,
+        )
+        .with(
+            "b_grower",
+            r#"
+            fn limits() { #{max_string_size: 64} }
+            let text = "";
+            fn load() {}
+            fn update(delta) { text += "0123456789"; }
+            "#,
+        )
+        .with(
+            "c_collector",
+            r#"
+            const LIMITS = #{max_array_size: 3};
+            fn load() {}
+            fn update(delta) {
+                let items = [];
+                for i in 0..1000 { items.push(i); }
+            }
+            "#,
+        )
+        .with(
+            "d_mapper",
+            r#"
+            const LIMITS = #{max_map_size: 2};
+            let table = #{};
+            fn load() {}
+            fn update(delta) { table["key" + table.len()] = 0; }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut systems = script_systems(scripts, world);
+
+    for _ in 0..10 {
+        for system in &mut systems {
+            system.run_now(world);
+        }
+    }
+
+    assert_eq!(exceeded_limit(&systems[0]), "call depth");
+    assert_eq!(exceeded_limit(&systems[1]), "string size");
+    assert_eq!(exceeded_limit(&systems[2]), "array size");
+    assert_eq!(exceeded_limit(&systems[3]), "map size");
+    assert!(systems.iter().all(|system| system.script().is_disabled()));
+}
+
+#[test]
+fn test_default_limits_restore_the_call_depth() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.set_limits(ScriptLimits {
+        max_call_depth: Some(4),
+        ..ScriptLimits::default()
+    });
+    assert_eq!(helper.world().fetch::<Engine>().max_call_levels(), 4);
+
+    helper.set_limits(ScriptLimits::default());
+    assert_eq!(
+        helper.world().fetch::<Engine>().max_call_levels(),
+        Engine::new_raw().max_call_levels()
+    );
+}
+
+#[test]
+fn test_global_limits_hold_locals_and_calls() {
+    let mut helper = WorldHelper::new(WorldExt::new());
+    helper.set_limits(ScriptLimits {
+        max_call_depth: Some(8),
+        max_string_size: Some(64),
+        max_array_size: Some(3),
+        max_variables: Some(6),
+        ..ScriptLimits::default()
+    });
+    let world = helper.world_mut();
+    world.insert(ResourceTable::new());
+
+    let (scripts, errors) = MemorySource::new()
+        .with(
+            "a_recurser",
+            r#"
+            fn load() {}
+            fn down() { down() }
+            fn update(delta) { down(); }
+            "#,
+        )
+        .with(
+            "b_grower",
+            r#"
+            let text = "";
+            fn load() {}
+            fn update(delta) { text += "0123456789"; }
+            "#,
+        )
+        .with(
+            "c_collector",
+            r#"
+            fn load() {}
+            fn update(delta) {
+                let items = [];
+                for i in 0..1000 { items.push(i); }
+            }
+            "#,
+        )
+        .with(
+            "d_hoarder",
+            r#"
+            fn load() {}
+            fn update(delta) {
+                let a = 1; let b = 2; let c = 3; let d = 4; let e = 5; let f = 6; let g = 7;
+            }
+            "#,
+        )
+        .load_scripts(&world.fetch::<Engine>());
+    assert!(errors.is_empty(), "{:?}", errors);
+    // setting the systems up doesn't drop the global limits
+    let mut systems = script_systems(scripts, world);
+
+    for _ in 0..10 {
+        for system in &mut systems {
+            system.run_now(world);
+        }
+    }
+
+    assert_eq!(exceeded_limit(&systems[0]), "call depth");
+    assert_eq!(exceeded_limit(&systems[1]), "string size");
+    assert_eq!(exceeded_limit(&systems[2]), "array size");
+    assert_eq!(exceeded_limit(&systems[3]), "variables");
+    assert!(systems.iter().all(|system| system.script().is_disabled()));
+}
+
+fn exceeded_limit(system: &ScriptSystem) -> String {
+    match system.last_error() {
+        Some(ScriptError::LimitExceeded { limit, .. }) => limit.clone(),
+        other => panic!("expected a limit error, got {:?}", other),
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{Scriptable, DEFAULT_FIXED_STEP};
+use specs::prelude::*;
+use std::time::Instant;
+
+/// The game clock shared by every system, advanced once per dispatch by `TimeSystem`.
+///
+/// Script systems take their delta and fixed step from it when it's in the world,
+/// instead of each script timing itself. Inserted with
+/// `WorldHelper::insert_scriptable_resource`, scripts can read it with
+/// `resource("Time")`, and pause or slow it down with `resource_mut("Time")` if they
+/// declare the write.
+#[derive(Scriptable, Clone, Debug)]
+pub struct Time {
+    /// the scaled seconds since the last tick, 0 while paused
+    #[scriptable(readonly)]
+    pub delta: f64,
+    /// the scaled seconds since the clock started
+    #[scriptable(readonly)]
+    pub total: f64,
+    /// how fast the clock runs compared to the wall clock
+    pub scale: f64,
+    pub paused: bool,
+    /// the length of a step of `fixed_update`, for scripts without a step of their own
+    /// from `Script::set_fixed_step`
+    pub fixed_step: f64,
+    /// how many times the clock was advanced
+    #[scriptable(readonly)]
+    pub tick: u64,
+    #[scriptable(skip)]
+    last_tick: Option<Instant>,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time {
+            delta: 0.0,
+            total: 0.0,
+            scale: 1.0,
+            paused: false,
+            fixed_step: DEFAULT_FIXED_STEP,
+            tick: 0,
+            last_tick: None,
+        }
+    }
+}
+
+impl Time {
+    /// advance the clock by the wall clock time since the last tick. The first tick
+    /// has no time to measure, its delta is 0.
+    pub fn advance(&mut self) {
+        let now = Instant::now();
+        let real = self
+            .last_tick
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_tick = Some(now);
+        self.advance_by(real);
+    }
+
+    /// advance the clock by `real` seconds of wall clock time
+    pub fn advance_by(&mut self, real: f64) {
+        self.delta = if self.paused { 0.0 } else { real * self.scale };
+        self.total += self.delta;
+        self.tick += 1;
+    }
+}
+
+/// Advances the `Time` resource, add it before the systems using it.
+pub struct TimeSystem;
+
+impl<'a> System<'a> for TimeSystem {
+    type SystemData = Write<'a, Time>;
+
+    fn run(&mut self, mut time: Self::SystemData) {
+        time.advance();
+    }
+}
@@ -0,0 +1,164 @@
+use rhai::{Dynamic, EvalAltResult, ImmutableString, FLOAT, INT};
+
+/// A field of a scriptable component, as seen by scripts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    /// the name scripts use for the field
+    pub name: &'static str,
+    /// the rust type of the field
+    pub type_name: &'static str,
+    /// whether scripts can only read the field
+    pub read_only: bool,
+}
+
+/// A method of a scriptable component, as seen by scripts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Method {
+    /// the name scripts call the method by
+    pub name: &'static str,
+    /// the rust types of the arguments
+    pub params: &'static [&'static str],
+    /// whether the method takes `&mut self`
+    pub mutable: bool,
+}
+
+/// Conversion between rust values and the values scripts work with.
+///
+/// Numbers are widened to rhai's `INT`/`FLOAT` so scripts can do arithmetic on
+/// them, and narrowed back when a script assigns them. Unsigned integers too large for
+/// an `INT` become `INT::MAX`.
+pub trait ScriptValue: Sized {
+    fn to_dynamic(&self) -> Dynamic;
+
+    fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>>;
+}
+
+/// the error for a script value that can't be converted to `T`
+pub fn mismatch<T>(value: &Dynamic) -> Box<EvalAltResult> {
+    Box::new(EvalAltResult::ErrorMismatchDataType(
+        std::any::type_name::<T>().to_owned(),
+        value.type_name().to_owned(),
+        rhai::Position::NONE,
+    ))
+}
+
+macro_rules! impl_int {
+    ($($ty:ty),*) => {$(
+        impl ScriptValue for $ty {
+            fn to_dynamic(&self) -> Dynamic {
+                // only unsigned values can be too large, they saturate instead of wrapping
+                Dynamic::from(INT::try_from(*self).unwrap_or(INT::MAX))
+            }
+
+            fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+                value
+                    .as_int()
+                    .ok()
+                    .and_then(|int| <$ty>::try_from(int).ok())
+                    .ok_or_else(|| mismatch::<$ty>(&value))
+            }
+        }
+    )*};
+}
+
+impl_int!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! impl_float {
+    ($($ty:ty),*) => {$(
+        impl ScriptValue for $ty {
+            fn to_dynamic(&self) -> Dynamic {
+                Dynamic::from(*self as FLOAT)
+            }
+
+            fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+                // accept integers too, `pos.x = 3` shouldn't be an error
+                value
+                    .as_float()
+                    .or_else(|_| value.as_int().map(|int| int as FLOAT))
+                    .map(|float| float as $ty)
+                    .map_err(|_| mismatch::<$ty>(&value))
+            }
+        }
+    )*};
+}
+
+impl_float!(f32, f64);
+
+macro_rules! impl_cast {
+    ($($ty:ty),*) => {$(
+        impl ScriptValue for $ty {
+            fn to_dynamic(&self) -> Dynamic {
+                Dynamic::from(self.clone())
+            }
+
+            fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+                let error = mismatch::<$ty>(&value);
+                value.try_cast().ok_or(error)
+            }
+        }
+    )*};
+}
+
+impl_cast!(bool, char, ImmutableString, rhai::Map);
+
+impl ScriptValue for String {
+    fn to_dynamic(&self) -> Dynamic {
+        Dynamic::from(self.clone())
+    }
+
+    fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+        let error = mismatch::<String>(&value);
+        value.into_string().map_err(|_| error)
+    }
+}
+
+impl ScriptValue for () {
+    fn to_dynamic(&self) -> Dynamic {
+        Dynamic::UNIT
+    }
+
+    fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+        let error = mismatch::<()>(&value);
+        value.try_cast().ok_or(error)
+    }
+}
+
+impl ScriptValue for Dynamic {
+    fn to_dynamic(&self) -> Dynamic {
+        self.clone()
+    }
+
+    fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+        Ok(value)
+    }
+}
+
+impl<T: ScriptValue> ScriptValue for Vec<T> {
+    fn to_dynamic(&self) -> Dynamic {
+        Dynamic::from_array(self.iter().map(T::to_dynamic).collect())
+    }
+
+    fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+        let error = mismatch::<Vec<T>>(&value);
+        value
+            .into_array()
+            .map_err(|_| error)?
+            .into_iter()
+            .map(T::from_dynamic)
+            .collect()
+    }
+}
+
+impl<T: ScriptValue> ScriptValue for Option<T> {
+    fn to_dynamic(&self) -> Dynamic {
+        self.as_ref().map_or(Dynamic::UNIT, T::to_dynamic)
+    }
+
+    fn from_dynamic(value: Dynamic) -> Result<Self, Box<EvalAltResult>> {
+        if value.is::<()>() {
+            Ok(None)
+        } else {
+            T::from_dynamic(value).map(Some)
+        }
+    }
+}